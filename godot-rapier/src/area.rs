@@ -7,8 +7,11 @@ use godot::{
 use rapier3d::prelude::*;
 
 use crate::{
-    collision_object::RapierCollisionObject, conversions::IntoExt, error::RapierError,
-    shapes::RapierShapeInstance, space::RapierSpace,
+    collision_object::{scale_approx_eq, RapierCollisionObject},
+    conversions::IntoExt,
+    error::RapierError,
+    shapes::RapierShapeInstance,
+    space::RapierSpace,
 };
 
 const DEFAULT_WIND_FORCE_MAGNITUDE: f32 = 0.0;
@@ -19,7 +22,8 @@ const DEFAULT_WIND_DIRECTION: Vector3 = Vector3::ZERO;
 
 pub struct SpaceInfo {
     pub space: Rc<RefCell<RapierSpace>>,
-    pub handle: ColliderHandle,
+    /// One collider per shape, in the same order as the shapes.
+    pub colliders: Vec<ColliderHandle>,
 }
 
 pub struct RapierArea {
@@ -89,7 +93,11 @@ impl RapierCollisionObject for RapierArea {
     fn remove_space(&mut self, remove_from_space: bool) {
         if remove_from_space {
             if let Some(space_info) = self.space_info() {
-                space_info.space.borrow_mut().remove_area(space_info.handle);
+                let mut space = space_info.space.borrow_mut();
+                for collider in &space_info.colliders {
+                    space.remove_collider(*collider);
+                }
+                space.remove_area(self.rid);
             }
         }
         self.space_info = None;
@@ -128,7 +136,7 @@ impl RapierCollisionObject for RapierArea {
         self.collision_layer = layer;
         if let Some(space_info) = self.space_info() {
             space_info.space.borrow_mut().set_area_collision_group(
-                space_info.handle,
+                &space_info.colliders,
                 self.collision_layer,
                 self.collision_mask,
             );
@@ -143,7 +151,7 @@ impl RapierCollisionObject for RapierArea {
         self.collision_mask = mask;
         if let Some(space_info) = self.space_info() {
             space_info.space.borrow_mut().set_area_collision_group(
-                space_info.handle,
+                &space_info.colliders,
                 self.collision_layer,
                 self.collision_mask,
             );
@@ -154,10 +162,39 @@ impl RapierCollisionObject for RapierArea {
         self.collision_mask
     }
 
-    fn update_shapes(&mut self) {
+    fn insert_shape_collider(&mut self, idx: usize) {
+        let Some(space) = self.space_info().map(|space_info| space_info.space.clone()) else {
+            return;
+        };
+        let collider = self.build_collider(idx);
+        let mut space = space.borrow_mut();
+        let handle = space.add_area_collider(collider);
+        if let Some(space_info) = self.space_info.as_mut() {
+            space_info.colliders.insert(idx, handle);
+            space.reindex_colliders(self.rid, &space_info.colliders);
+        }
+    }
+
+    fn remove_shape_collider(&mut self, idx: usize) {
+        if let Some(space_info) = self.space_info.as_mut() {
+            if idx < space_info.colliders.len() {
+                let handle = space_info.colliders.remove(idx);
+                let mut space = space_info.space.borrow_mut();
+                space.remove_collider(handle);
+                space.reindex_colliders(self.rid, &space_info.colliders);
+            }
+        }
+    }
+
+    fn update_shape_collider(&mut self, idx: usize) {
         if let Some(space_info) = self.space_info() {
-            let mut space = space_info.space.borrow_mut();
-            space.update_area_shape(space_info.handle, self.build_shared_shape());
+            if let Some(handle) = space_info.colliders.get(idx) {
+                let collider = self.build_collider(idx);
+                space_info
+                    .space
+                    .borrow_mut()
+                    .update_collider(*handle, &collider);
+            }
         }
     }
     fn transform(&self) -> Transform3D {
@@ -167,8 +204,14 @@ impl RapierCollisionObject for RapierArea {
 
 #[allow(clippy::default_trait_access)]
 impl RapierArea {
-    pub fn set_space(&mut self, space: Rc<RefCell<RapierSpace>>, handle: ColliderHandle) {
-        self.space_info = Some(SpaceInfo { space, handle });
+    pub fn set_space(&mut self, space: Rc<RefCell<RapierSpace>>) {
+        self.space_info = Some(SpaceInfo {
+            space,
+            colliders: Vec::default(),
+        });
+        for idx in 0..self.shapes.len() {
+            self.insert_shape_collider(idx);
+        }
     }
     pub const fn space_info(&self) -> Option<&SpaceInfo> {
         self.space_info.as_ref()
@@ -182,13 +225,17 @@ impl RapierArea {
     }
 
     pub fn set_transform(&mut self, transform: Transform3D) {
+        let previous_scale = self.scale();
         self.transform = transform;
+        if !scale_approx_eq(self.scale(), previous_scale) {
+            self.update_shapes();
+            return;
+        }
         if let Some(space_info) = self.space_info() {
-            let (isometry, _) = transform.into_ext();
-            space_info
-                .space
-                .borrow_mut()
-                .set_area_isometry(space_info.handle, isometry);
+            let mut space = space_info.space.borrow_mut();
+            for (handle, shape_instance) in space_info.colliders.iter().zip(&self.shapes) {
+                space.set_collider_position(*handle, self.collider_position(shape_instance));
+            }
         }
     }
 
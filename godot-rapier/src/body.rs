@@ -17,7 +17,8 @@ use rapier3d::prelude::*;
 
 use crate::{
     area::RapierArea,
-    collision_object::RapierCollisionObject,
+    collision_object::{scale_approx_eq, RapierCollisionObject},
+    contact_modifiers::SurfaceVelocity,
     conversions::{FromExt, IntoExt},
     direct_body_state_3d::RapierPhysicsDirectBodyState3D,
     error::RapierError,
@@ -25,9 +26,61 @@ use crate::{
     space::RapierSpace,
 };
 
+/// Godot's damp of a body: the damps of the areas it is in, by priority, until one replaces the
+/// ones below it, the damp of the space unless an area replaced it, then the body's own damp.
+fn total_damp(
+    damp_mode: DampMode,
+    damp: f32,
+    areas: impl IntoIterator<Item = (AreaSpaceOverrideMode, f32)>,
+    default_damp: f32,
+) -> f32 {
+    let mut total_damp = 0.0;
+    let mut damp_done = false;
+    for (area_damp_mode, area_damp) in areas {
+        damp_done = match area_damp_mode {
+            AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE => {
+                total_damp += area_damp;
+                false
+            }
+            AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE_REPLACE => {
+                total_damp += area_damp;
+                true
+            }
+            AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE => {
+                total_damp = area_damp;
+                true
+            }
+            AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE_COMBINE => {
+                total_damp = area_damp;
+                false
+            }
+            _ => false,
+        };
+        if damp_done {
+            break;
+        }
+    }
+    if !damp_done {
+        total_damp += default_damp;
+    }
+    match damp_mode {
+        DampMode::DAMP_MODE_COMBINE => total_damp + damp,
+        DampMode::DAMP_MODE_REPLACE => damp,
+        _ => total_damp,
+    }
+}
+
+/// Velocity after a step of Godot's damping, which stops the body outright when the damp is
+/// higher than the step rate.
+fn damped_velocity(velocity: Vector<f32>, damp: f32, step: f32) -> Vector<f32> {
+    velocity * (1.0 - step * damp).max(0.0)
+}
+
 pub struct SpaceInfo {
     pub space: Rc<RefCell<RapierSpace>>,
     pub handle: RigidBodyHandle,
+    /// One collider per shape, in the same order as the shapes.
+    pub colliders: Vec<ColliderHandle>,
 }
 #[allow(clippy::struct_excessive_bools)]
 pub struct RapierBody {
@@ -51,6 +104,7 @@ pub struct RapierBody {
     collision_layer: u32,
     collision_mask: u32,
     collision_priority: f32,
+    collision_exceptions: Vec<Rid>,
 
     bounce: f32,
     friction: f32,
@@ -68,6 +122,8 @@ pub struct RapierBody {
 
     linear_velocity: Vector3,
     angular_velocity: Vector3,
+    constant_linear_velocity: Vector3,
+    constant_angular_velocity: Vector3,
     transform: Transform3D,
     kinematic_isometry: Isometry<f32>,
     is_sleeping: bool,
@@ -147,21 +203,78 @@ impl RapierCollisionObject for RapierBody {
         self.collision_mask
     }
 
-    fn update_shapes(&mut self) {
+    fn insert_shape_collider(&mut self, idx: usize) {
+        let Some(space) = self.space_info().map(|space_info| space_info.space.clone()) else {
+            return;
+        };
+        let mut collider = self.build_collider(idx);
+        collider.set_friction(self.friction);
+        collider.set_restitution(self.bounce);
+        {
+            let mut space = space.borrow_mut();
+            if let Some(space_info) = self.space_info.as_mut() {
+                let handle = space.add_body_collider(space_info.handle, collider);
+                space_info.colliders.insert(idx, handle);
+                space.reindex_colliders(self.rid, &space_info.colliders);
+            }
+        }
+        self.update_one_way_collision(idx);
+        self.update_separation_ray(idx);
+        self.update_surface_velocity();
+        self.update_mass_properties();
+    }
+
+    fn remove_shape_collider(&mut self, idx: usize) {
+        if let Some(space_info) = self.space_info.as_mut() {
+            if idx < space_info.colliders.len() {
+                let handle = space_info.colliders.remove(idx);
+                let mut space = space_info.space.borrow_mut();
+                space.remove_collider(handle);
+                space.reindex_colliders(self.rid, &space_info.colliders);
+            }
+        }
+        self.update_mass_properties();
+    }
+
+    fn update_shape_collider(&mut self, idx: usize) {
         if let Some(space_info) = self.space_info() {
-            let mut space = space_info.space.borrow_mut();
-            space.update_body_shape(space_info.handle, self.build_shared_shape());
+            if let Some(handle) = space_info.colliders.get(idx) {
+                let collider = self.build_collider(idx);
+                space_info
+                    .space
+                    .borrow_mut()
+                    .update_collider(*handle, &collider);
+            }
         }
+        self.update_separation_ray(idx);
+        self.update_mass_properties();
+    }
+
+    fn collider_position(&self, shape_instance: &RapierShapeInstance) -> Isometry<f32> {
+        shape_instance.geometry_isometry(self.scale())
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.is_rigid()
     }
 
     fn transform(&self) -> Transform3D {
         if let Some(space_info) = self.space_info() {
             if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
-                return (*body.position()).into_ext();
+                let mut transform: Transform3D = (*body.position()).into_ext();
+                let scale = self.scale();
+                transform.basis = transform.basis * Basis::from_diagonal(scale.x, scale.y, scale.z);
+                return transform;
             }
         }
         self.transform
     }
+
+    /// Rapier bodies have no scale, it is kept from the last transform Godot set.
+    fn scale(&self) -> Vector<f32> {
+        let (_, scale) = self.transform.into_ext();
+        scale
+    }
 }
 
 impl RapierBody {
@@ -183,6 +296,7 @@ impl RapierBody {
             collision_layer: 1,
             collision_mask: 1,
             collision_priority: 1.0,
+            collision_exceptions: Vec::default(),
             bounce: Default::default(),
             friction: Default::default(),
             mass: 1.0,
@@ -197,6 +311,8 @@ impl RapierBody {
             areas: Vec::default(),
             linear_velocity: Vector3::default(),
             angular_velocity: Vector3::default(),
+            constant_linear_velocity: Vector3::default(),
+            constant_angular_velocity: Vector3::default(),
             transform: Transform3D::IDENTITY,
             kinematic_isometry: Isometry::default(),
             is_sleeping: false,
@@ -205,7 +321,15 @@ impl RapierBody {
         }
     }
     pub fn set_space_info(&mut self, space: Rc<RefCell<RapierSpace>>, handle: RigidBodyHandle) {
-        self.space_info = Some(SpaceInfo { space, handle });
+        self.space_info = Some(SpaceInfo {
+            space,
+            handle,
+            colliders: Vec::default(),
+        });
+        for idx in 0..self.shapes.len() {
+            self.insert_shape_collider(idx);
+        }
+        self.update_mass_properties();
     }
 
     pub const fn space_info(&self) -> Option<&SpaceInfo> {
@@ -228,6 +352,64 @@ impl RapierBody {
         self.constant_torque += (position - center_of_mass_relative).cross(&force);
     }
 
+    pub fn add_collision_exception(&mut self, excepted: Rid) {
+        if self.collision_exceptions.contains(&excepted) {
+            return;
+        }
+        self.collision_exceptions.push(excepted);
+        if let Some(space_info) = self.space_info() {
+            space_info
+                .space
+                .borrow_mut()
+                .add_collision_exception(self.rid, excepted);
+        }
+    }
+
+    pub fn set_shape_one_way_direction(&mut self, idx: usize, direction: Option<Vector3>) {
+        if let Some(shape) = self.shapes.get_mut(idx) {
+            shape.one_way_direction = direction.map(|direction| {
+                let direction: Vector<f32> = direction.into_ext();
+                direction.normalize()
+            });
+            self.update_one_way_collision(idx);
+        } else {
+            godot_error!("{}", RapierError::ShapeNotInObject(idx, self.rid));
+        }
+    }
+
+    pub fn shape_one_way_direction(&self, idx: usize) -> Option<Vector3> {
+        self.one_way_direction(idx).map(Vector3::from_ext)
+    }
+
+    /// One-way direction of a shape, in shape local space.
+    pub fn one_way_direction(&self, idx: usize) -> Option<Vector<f32>> {
+        self.shapes.get(idx)?.one_way_direction
+    }
+
+    fn update_separation_ray(&self, idx: usize) {
+        if let Some(space_info) = self.space_info() {
+            if let (Some(handle), Some(shape)) =
+                (space_info.colliders.get(idx), self.shapes.get(idx))
+            {
+                space_info
+                    .space
+                    .borrow_mut()
+                    .set_separation_ray(*handle, shape.separation_ray());
+            }
+        }
+    }
+
+    fn update_one_way_collision(&self, idx: usize) {
+        if let Some(space_info) = self.space_info() {
+            if let Some(handle) = space_info.colliders.get(idx) {
+                space_info
+                    .space
+                    .borrow_mut()
+                    .set_one_way_collision(*handle, self.one_way_direction(idx));
+            }
+        }
+    }
+
     pub fn add_constant_torque(&mut self, torque: Vector3) {
         self.constant_torque += Vector::from_ext(torque);
     }
@@ -237,6 +419,9 @@ impl RapierBody {
     }
 
     pub fn angular_velocity(&self) -> Vector3 {
+        if self.is_static() {
+            return self.constant_angular_velocity;
+        }
         if let Some(space_info) = self.space_info() {
             if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
                 return (*body.angvel()).into_ext();
@@ -314,14 +499,26 @@ impl RapierBody {
         self.bounce
     }
 
+    /// The force integration callback with its arguments, if it must run this step. The space
+    /// calls it before the body integrates its forces, with the body no longer borrowed.
+    pub fn force_integration_call(&self) -> Option<(Callable, VariantArray)> {
+        let direct_state = self.direct_state.as_ref()?;
+        if !self.is_rigid() || !self.custom_integrator_callback.is_valid() || self.is_sleeping() {
+            return None;
+        }
+        let args = if self.custom_integrator_userdata.is_nil() {
+            array![direct_state.to_variant()]
+        } else {
+            array![
+                direct_state.to_variant(),
+                self.custom_integrator_userdata.clone()
+            ]
+        };
+        Some((self.custom_integrator_callback.clone(), args))
+    }
+
     pub fn call_queries(&mut self) {
         if let Some(direct_state) = &self.direct_state {
-            if self.is_rigid() && self.custom_integrator_callback.is_valid() {
-                self.custom_integrator_callback.callv(array![
-                    direct_state.to_variant(),
-                    self.custom_integrator_userdata.clone()
-                ]);
-            }
             if self.sync_state && self.body_state_callback.is_valid() {
                 self.body_state_callback
                     .callv(array![direct_state.to_variant()]);
@@ -340,6 +537,9 @@ impl RapierBody {
         }
         Vector3::ZERO
     }
+    pub const fn collision_exceptions(&self) -> &Vec<Rid> {
+        &self.collision_exceptions
+    }
     pub const fn collision_priority(&self) -> f32 {
         self.collision_priority
     }
@@ -409,52 +609,60 @@ impl RapierBody {
                     return;
                 }
             }
-            let linear_velocity = Vector::from_ext(self.linear_velocity());
-            let mut space = space_info.space.borrow_mut();
-
             if !self.has_custom_integrator {
-                space.set_linear_velocity(
-                    space_info.handle,
-                    linear_velocity + Vector::from_ext(step * self.total_gravity()),
+                // Damped like in Godot, before gravity is added by the solver. Rapier's own
+                // damping, which decays differently, is left off. None of it wakes the body.
+                let linear_velocity = damped_velocity(
+                    self.linear_velocity().into_ext(),
+                    self.total_linear_damp(),
+                    step,
+                );
+                let angular_velocity = damped_velocity(
+                    self.angular_velocity().into_ext(),
+                    self.total_angular_damp(),
+                    step,
                 );
+                let gravity = self.total_gravity();
+
+                let mut space = space_info.space.borrow_mut();
+                space.set_linear_velocity(space_info.handle, linear_velocity, false);
+                space.set_angular_velocity(space_info.handle, angular_velocity, false);
+                space.apply_gravity(space_info.handle, gravity.into_ext());
                 space.apply_central_force(space_info.handle, self.constant_force);
                 space.apply_torque(space_info.handle, self.constant_torque);
             }
         }
-        self.sync_state = true;
     }
 
+    /// Inverse of the principal inertia, zero for bodies that cannot rotate.
     pub fn inverse_inertia(&self) -> Vector3 {
-        if self.is_kinematic() || self.is_static() {
+        if self.body_mode != BodyMode::BODY_MODE_RIGID {
             return Vector3::ZERO;
         }
         if let Some(space_info) = self.space_info() {
-            if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
-                let inv_inertia = body
-                    .mass_properties()
-                    .local_mprops
-                    .inv_principal_inertia_sqrt;
-                return Vector3::new(inv_inertia.x, inv_inertia.y, inv_inertia.z);
+            let space = space_info.space.borrow();
+            if let Some(inv_inertia) = space.inverse_principal_inertia(space_info.handle) {
+                return inv_inertia.into_ext();
             }
         }
         self.inertia.inverse()
     }
 
+    /// World space inverse inertia tensor, the inverse principal inertia rotated into the
+    /// principal inertia axes.
     pub fn inverse_inertia_tensor(&self) -> Basis {
-        if self.is_kinematic() || self.is_static() {
-            return Basis::IDENTITY;
+        if self.body_mode != BodyMode::BODY_MODE_RIGID {
+            return Basis::from_diagonal(0.0, 0.0, 0.0);
         }
         if let Some(space_info) = self.space_info() {
-            if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
-                let inv_inertia = body
-                    .mass_properties()
-                    .local_mprops
-                    .inv_principal_inertia_sqrt;
-                return Basis::from_diagonal(inv_inertia.x, inv_inertia.y, inv_inertia.z);
+            let space = space_info.space.borrow();
+            if let Some(tensor) = space.inverse_inertia_tensor(space_info.handle) {
+                return tensor.into_ext();
             }
         }
-        let inv = self.inertia.inverse();
-        Basis::from_diagonal(inv.x, inv.y, inv.z)
+        let axes = self.principal_inertia_axes();
+        let inv_inertia = self.inverse_inertia();
+        axes * Basis::from_diagonal(inv_inertia.x, inv_inertia.y, inv_inertia.z) * axes.transposed()
     }
 
     pub fn inverse_mass(&self) -> f32 {
@@ -495,6 +703,9 @@ impl RapierBody {
         self.linear_damp
     }
     pub fn linear_velocity(&self) -> Vector3 {
+        if self.is_static() {
+            return self.constant_linear_velocity;
+        }
         if let Some(space_info) = self.space_info() {
             if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
                 return (*body.linvel()).into_ext();
@@ -516,19 +727,16 @@ impl RapierBody {
         self.mass
     }
 
+    /// Position based kinematic bodies ignore the velocities they are given, Rapier derives them
+    /// from the successive target positions instead.
     fn move_kinematic(&mut self) {
-        if let Some(space_info) = self.space_info() {
+        let moves = self.space_info().is_some_and(|space_info| {
             let mut space = space_info.space.borrow_mut();
-            space.set_linear_velocity(space_info.handle, Vector::zeros());
-            space.set_angular_velocity(space_info.handle, Vector::zeros());
-
-            if self.isometry() == self.kinematic_isometry {
-                return;
-            }
-
-            space.move_kinematic(space_info.handle, self.kinematic_isometry);
+            space.move_kinematic(space_info.handle, self.kinematic_isometry)
+        });
+        if moves {
+            self.sync_state = true;
         }
-        self.sync_state = true;
     }
 
     pub fn pre_step(&mut self, step: f32) {
@@ -542,30 +750,39 @@ impl RapierBody {
             _ => {}
         };
     }
+    /// World space rotation of the principal inertia axes.
     pub fn principal_inertia_axes(&self) -> Basis {
         if self.is_kinematic() || self.is_static() {
             return Basis::IDENTITY;
         }
-
         if let Some(space_info) = self.space_info() {
-            if let Some(body) = space_info.space.borrow().get_body(space_info.handle) {
-                let inertia = body.mass_properties().local_mprops.principal_inertia();
-                return self.transform().basis
-                    * Basis::from_diagonal(inertia.x, inertia.y, inertia.z);
+            let space = space_info.space.borrow();
+            if let Some(axes) = space.principal_inertia_axes(space_info.handle) {
+                return axes.into_ext();
             }
         }
-        self.transform().basis
-            * Basis::from_diagonal(self.inertia.x, self.inertia.y, self.inertia.z)
+        self.transform().basis.orthonormalized()
+    }
+
+    /// Applies the mass properties, again after the colliders of the body changed. A zero
+    /// inertia and a center of mass that is not custom are derived from the shapes.
+    fn update_mass_properties(&self) {
+        if let Some(space_info) = self.space_info() {
+            space_info.space.borrow_mut().set_mass_properties(
+                space_info.handle,
+                self.mass,
+                self.has_custom_center_of_mass
+                    .then_some(self.custom_center_of_mass),
+                (self.inertia != Vector3::ZERO).then_some(self.inertia),
+            );
+        }
     }
 
     pub fn reset_mass_properties(&mut self) {
         self.inertia = Vector3::ZERO;
         self.custom_center_of_mass = Vector3::ZERO;
         self.has_custom_center_of_mass = false;
-        if let Some(space_info) = self.space_info() {
-            let mut space = space_info.space.borrow_mut();
-            space.set_mass(space_info.handle, self.mass, false);
-        }
+        self.update_mass_properties();
     }
 
     pub fn set_angular_damp(&mut self, angular_damp: f32) {
@@ -573,31 +790,81 @@ impl RapierBody {
             return;
         }
         self.angular_damp = angular_damp;
-        self.update_damp();
     }
 
     pub fn set_angular_velocity(&mut self, value: Vector3) {
+        if !self.is_rigid() {
+            self.constant_angular_velocity = value;
+            self.update_surface_velocity();
+            return;
+        }
         self.angular_velocity = value;
         if let Some(space_info) = self.space_info() {
             let mut space = space_info.space.borrow_mut();
-            space.set_angular_velocity(space_info.handle, value.into_ext());
+            space.set_angular_velocity(space_info.handle, value.into_ext(), true);
         }
     }
 
+    /// Follows Godot when freezing a rigid body, with either freeze mode: the body stops, and
+    /// starts again from rest when it is unfrozen, with the mass properties it had. A body turning
+    /// kinematic stays where it is until it is moved.
     pub fn set_body_mode(&mut self, mode: BodyMode) {
         if self.body_mode == mode {
             return;
         }
+        let was_rigid = self.is_rigid();
         self.body_mode = mode;
+        if !self.is_rigid() {
+            self.linear_velocity = Vector3::ZERO;
+            self.angular_velocity = Vector3::ZERO;
+        }
         if let Some(space_info) = self.space_info() {
             space_info
                 .space
                 .borrow_mut()
                 .set_body_mode(space_info.handle, mode);
+        }
+        if self.is_kinematic() {
+            self.kinematic_isometry = self.isometry();
+        }
+        if was_rigid != self.is_rigid() {
+            // Rebuilds the colliders, which may only be concave when not rigid, and reapplies the
+            // mass properties over them.
+            self.update_shapes();
+        }
+        self.update_surface_velocity();
+    }
 
-            if self.is_kinematic() {
-                self.kinematic_isometry = self.isometry();
-            }
+    /// Velocity static and kinematic bodies give to the objects touching them, set through
+    /// their linear and angular velocity states.
+    pub fn surface_velocity(&self) -> Option<SurfaceVelocity> {
+        if self.is_rigid()
+            || (self.constant_linear_velocity == Vector3::ZERO
+                && self.constant_angular_velocity == Vector3::ZERO)
+        {
+            return None;
+        }
+        Some(SurfaceVelocity::new(
+            self.constant_linear_velocity.into_ext(),
+            self.constant_angular_velocity.into_ext(),
+        ))
+    }
+
+    fn update_surface_velocity(&self) {
+        if let Some(space_info) = self.space_info() {
+            let (linear_velocity, angular_velocity) = if self.is_rigid() {
+                (Vector::zeros(), Vector::zeros())
+            } else {
+                (
+                    self.constant_linear_velocity.into_ext(),
+                    self.constant_angular_velocity.into_ext(),
+                )
+            };
+            space_info.space.borrow_mut().set_surface_velocity(
+                space_info.handle,
+                linear_velocity,
+                angular_velocity,
+            );
         }
     }
 
@@ -613,6 +880,8 @@ impl RapierBody {
         self.custom_integrator_userdata = userdata;
     }
 
+    /// Godot passes the bounce of an absorbent `PhysicsMaterial` as a negative value, it stays
+    /// signed on the colliders for `combine_materials`.
     pub fn set_bounce(&mut self, bounce: f32) {
         self.bounce = bounce;
         if let Some(space_info) = self.space_info() {
@@ -635,11 +904,7 @@ impl RapierBody {
 
         self.custom_center_of_mass = center_of_mass;
         self.has_custom_center_of_mass = true;
-
-        if let Some(space_info) = self.space_info() {
-            let mut space = space_info.space.borrow_mut();
-            space.set_custom_center_of_mass(space_info.handle, center_of_mass);
-        }
+        self.update_mass_properties();
     }
     pub fn set_collision_priority(&mut self, priority: f32) {
         self.collision_priority = priority;
@@ -665,6 +930,8 @@ impl RapierBody {
         }
     }
 
+    /// Godot passes the friction of a rough `PhysicsMaterial` as a negative value, it stays
+    /// signed on the colliders for `combine_materials`.
     pub fn set_friction(&mut self, friction: f32) {
         self.friction = friction;
         if let Some(space_info) = self.space_info() {
@@ -683,15 +950,16 @@ impl RapierBody {
 
     pub fn set_inertia(&mut self, inertia: Vector3) {
         self.inertia = inertia;
+        self.update_mass_properties();
+    }
 
-        if let Some(space_info) = self.space_info() {
-            let mut space = space_info.space.borrow_mut();
-            if inertia == Vector3::ZERO {
-                space.set_mass(space_info.handle, self.mass, self.has_custom_center_of_mass);
-            } else {
-                space.set_inertia(space_info.handle, inertia);
-            }
+    /// Marks the body for the state sync callback after a step it was active in. It moved
+    /// unless Rapier left it asleep, Godot then only needs to learn that it fell asleep.
+    pub fn update_after_step(&mut self, sleeping: bool) {
+        if !sleeping || self.is_sleeping != sleeping {
+            self.sync_state = true;
         }
+        self.is_sleeping = sleeping;
     }
 
     pub fn set_is_sleeping(&mut self, value: bool) {
@@ -707,26 +975,24 @@ impl RapierBody {
             return;
         }
         self.linear_damp = linear_damp;
-        self.update_damp();
     }
 
     pub fn set_linear_velocity(&mut self, value: Vector3) {
+        if !self.is_rigid() {
+            self.constant_linear_velocity = value;
+            self.update_surface_velocity();
+            return;
+        }
         self.linear_velocity = value;
         if let Some(space_info) = self.space_info() {
             let mut space = space_info.space.borrow_mut();
-            space.set_linear_velocity(space_info.handle, value.into_ext());
+            space.set_linear_velocity(space_info.handle, value.into_ext(), true);
         }
     }
 
     pub fn set_mass(&mut self, mass: f32) {
         self.mass = mass;
-        if let Some(space_info) = self.space_info() {
-            space_info.space.borrow_mut().set_mass(
-                space_info.handle,
-                mass,
-                self.has_custom_center_of_mass,
-            );
-        }
+        self.update_mass_properties();
     }
 
     pub fn set_param(&mut self, param: BodyParameter, value: &Variant) {
@@ -775,64 +1041,40 @@ impl RapierBody {
     }
 
     pub fn set_transform(&mut self, value: Transform3D) {
+        let previous_scale = self.scale();
         self.transform = value;
-        dbg!();
         if self.is_kinematic() {
-            self.kinematic_isometry = {
-                let (iso, _) = value.into_ext();
-                iso
-            };
-            return;
-        }
-
-        if let Some(space_info) = self.space_info() {
+            let (isometry, _) = value.into_ext();
+            self.kinematic_isometry = isometry;
+        } else if let Some(space_info) = self.space_info() {
             let mut space = space_info.space.borrow_mut();
             space.set_transform(space_info.handle, value);
         }
-    }
-
-    pub fn total_angular_damp(&self) -> f32 {
-        let mut total_angular_damp = 0.0;
-        let mut angular_damp_done = self.angular_damp_mode == DampMode::DAMP_MODE_REPLACE;
-        for area in &self.areas {
-            angular_damp_done = match area.borrow().angular_damp_mode() {
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE => {
-                    total_angular_damp += area.borrow().angular_damp();
-                    false
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE_REPLACE => {
-                    total_angular_damp += area.borrow().angular_damp();
-                    true
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE => {
-                    total_angular_damp = area.borrow().angular_damp();
-                    true
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE_COMBINE => {
-                    total_angular_damp = area.borrow().angular_damp();
-                    false
-                }
-                _ => false,
-            };
-            if angular_damp_done {
-                break;
-            }
-        }
-
-        if !angular_damp_done {
-            if let Some(space_info) = self.space_info() {
-                if let Some(default_area) = space_info.space.borrow().default_area() {
-                    total_angular_damp += default_area.borrow().angular_damp();
-                }
-            }
+        let scale = self.scale();
+        if scale_approx_eq(scale, previous_scale) {
+            return;
         }
-        match self.angular_damp_mode {
-            DampMode::DAMP_MODE_COMBINE => total_angular_damp += self.angular_damp,
-            DampMode::DAMP_MODE_REPLACE => total_angular_damp = self.angular_damp,
-            _ => {}
+        if self.is_rigid() && !(scale.x.approx_eq(&scale.y) && scale.x.approx_eq(&scale.z)) {
+            godot_warn!(
+                "Non-uniform scale of a rigid body is not supported by Godot Rapier. Its shapes \
+                 may not collide as expected."
+            );
         }
+        self.update_shapes();
+    }
 
-        total_angular_damp
+    pub fn total_angular_damp(&self) -> f32 {
+        let areas = self.areas.iter().map(|area| {
+            let area = area.borrow();
+            (area.angular_damp_mode(), area.angular_damp())
+        });
+        let (_, default_damp) = self.default_damps();
+        total_damp(
+            self.angular_damp_mode,
+            self.angular_damp,
+            areas,
+            default_damp,
+        )
     }
 
     pub fn total_gravity(&self) -> Vector3 {
@@ -875,60 +1117,23 @@ impl RapierBody {
     }
 
     pub fn total_linear_damp(&self) -> f32 {
-        let mut total_linear_damp = 0.0;
-        let mut linear_damp_done = self.linear_damp_mode == DampMode::DAMP_MODE_REPLACE;
-        for area in &self.areas {
-            linear_damp_done = match area.borrow().linear_damp_mode() {
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE => {
-                    total_linear_damp += area.borrow().linear_damp();
-                    false
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE_REPLACE => {
-                    total_linear_damp += area.borrow().linear_damp();
-                    true
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE => {
-                    total_linear_damp = area.borrow().linear_damp();
-                    true
-                }
-                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE_COMBINE => {
-                    total_linear_damp = area.borrow().linear_damp();
-                    false
-                }
-                _ => false,
-            };
-            if linear_damp_done {
-                break;
-            }
-        }
-
-        if !linear_damp_done {
-            if let Some(space_info) = self.space_info() {
-                if let Some(default_area) = space_info.space.borrow().default_area() {
-                    total_linear_damp += default_area.borrow().linear_damp();
-                }
-            }
-        }
-        match self.linear_damp_mode {
-            DampMode::DAMP_MODE_COMBINE => total_linear_damp += self.linear_damp,
-            DampMode::DAMP_MODE_REPLACE => total_linear_damp = self.linear_damp,
-            _ => {}
-        }
-
-        total_linear_damp
+        let areas = self.areas.iter().map(|area| {
+            let area = area.borrow();
+            (area.linear_damp_mode(), area.linear_damp())
+        });
+        let (default_damp, _) = self.default_damps();
+        total_damp(self.linear_damp_mode, self.linear_damp, areas, default_damp)
     }
 
-    pub fn update_damp(&self) {
-        if self.has_custom_integrator {
-            return;
-        }
+    /// Linear and angular damp of the default area of the space.
+    fn default_damps(&self) -> (f32, f32) {
         if let Some(space_info) = self.space_info() {
-            let total_linear_damp = self.total_linear_damp();
-            let total_angular_damp = self.total_angular_damp();
-            let mut space = space_info.space.borrow_mut();
-            space.set_linear_damp(space_info.handle, total_linear_damp);
-            space.set_angular_damp(space_info.handle, total_angular_damp);
+            if let Some(default_area) = space_info.space.borrow().default_area() {
+                let default_area = default_area.borrow();
+                return (default_area.linear_damp(), default_area.angular_damp());
+            }
         }
+        (0.0, 0.0)
     }
 
     pub const fn has_custom_integrator(&self) -> bool {
@@ -942,24 +1147,112 @@ impl RapierBody {
 
             space.reset_forces(space_info.handle);
             space.reset_torques(space_info.handle);
-
-            if self.has_custom_integrator {
-                space.set_linear_damp(space_info.handle, 0.0);
-                space.set_angular_damp(space_info.handle, 0.0);
-            } else {
-                space.set_linear_damp(space_info.handle, self.total_linear_damp());
-                space.set_angular_damp(space_info.handle, self.total_angular_damp());
-            }
         }
     }
 
     pub fn add_area(&mut self, area: Rc<RefCell<RapierArea>>) {
         self.areas.push(area);
-        self.update_damp();
+    }
+
+    pub fn remove_collision_exception(&mut self, excepted: Rid) {
+        self.collision_exceptions.retain(|rid| *rid != excepted);
+        if let Some(space_info) = self.space_info() {
+            space_info
+                .space
+                .borrow_mut()
+                .remove_collision_exception(self.rid, excepted);
+        }
     }
 
     pub fn remove_area(&mut self, area_rid: Rid) {
         self.areas.retain(|a| a.borrow().rid() != area_rid);
-        self.update_damp();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1.0 / 60.0;
+
+    /// Speed of a body damped by `damp` from a speed of 1 after each of `steps` steps.
+    fn decay_curve(damp: f32, steps: usize) -> Vec<f32> {
+        let mut velocity = vector![1.0, 0.0, 0.0];
+        (0..steps)
+            .map(|_| {
+                velocity = damped_velocity(velocity, damp, STEP);
+                velocity.x
+            })
+            .collect()
+    }
+
+    /// Checks the speed after some steps against `(step, speed)` pairs, the speeds Godot Physics
+    /// gives a body thrown at 1 m/s without gravity at 60 steps per second, worked out by hand.
+    fn assert_decays_like_godot(damp: f32, reference: &[(usize, f32)]) {
+        let curve = decay_curve(damp, 60);
+        for (step, expected) in reference {
+            let speed = curve[step - 1];
+            assert!(
+                (speed - expected).abs() < 1.0e-5,
+                "{speed} != {expected} after {step} steps"
+            );
+        }
+    }
+
+    #[test]
+    fn combined_damps_add_up() {
+        let areas = [(AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE, 2.0)];
+        let damp = total_damp(DampMode::DAMP_MODE_COMBINE, 1.0, areas, 0.5);
+        assert!((damp - 3.5).abs() < 1.0e-6);
+        assert_decays_like_godot(
+            damp,
+            &[
+                (1, 0.941_667),
+                (10, 0.548_242),
+                (30, 0.164_784),
+                (60, 0.027_154),
+            ],
+        );
+    }
+
+    #[test]
+    fn replaced_damps_ignore_the_areas_and_the_space() {
+        let areas = [(AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE, 2.0)];
+        let damp = total_damp(DampMode::DAMP_MODE_REPLACE, 1.0, areas, 0.5);
+        assert!((damp - 1.0).abs() < 1.0e-6);
+        assert_decays_like_godot(
+            damp,
+            &[
+                (1, 0.983_333),
+                (10, 0.845_294),
+                (30, 0.603_980),
+                (60, 0.364_792),
+            ],
+        );
+    }
+
+    #[test]
+    fn replacing_areas_hide_the_areas_below_and_the_space() {
+        let areas = [
+            (AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE, 2.0),
+            (AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE, 4.0),
+        ];
+        let damp = total_damp(DampMode::DAMP_MODE_COMBINE, 1.0, areas, 0.5);
+        assert!((damp - 3.0).abs() < 1.0e-6);
+
+        let areas = [
+            (
+                AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_REPLACE_COMBINE,
+                2.0,
+            ),
+            (AreaSpaceOverrideMode::AREA_SPACE_OVERRIDE_COMBINE, 4.0),
+        ];
+        let damp = total_damp(DampMode::DAMP_MODE_COMBINE, 1.0, areas, 0.5);
+        assert!((damp - 7.5).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn damps_above_the_step_rate_stop_the_body() {
+        assert_eq!(decay_curve(1.0 / STEP + 1.0, 2), vec![0.0, 0.0]);
     }
 }
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc};
 
-use godot::prelude::*;
+use godot::prelude::{math::ApproxEq, *};
 use rapier3d::prelude::*;
 
 use crate::{
@@ -9,6 +9,30 @@ use crate::{
     shapes::{RapierShape, RapierShapeInstance},
 };
 
+/// Rapier user data identifying the collision object, and the shape for a collider, that owns
+/// a collider or rigid body.
+pub fn owner_user_data(owner: Rid, shape_idx: usize) -> u128 {
+    u128::from(owner.to_u64()) | ((shape_idx as u128) << 64)
+}
+
+/// Rid of the collision object stored in a collider or rigid body user data.
+#[allow(clippy::cast_possible_truncation)]
+pub fn owner_rid(user_data: u128) -> Rid {
+    Rid::new(user_data as u64)
+}
+
+/// Index in its owner of the shape stored in a collider user data.
+#[allow(clippy::cast_possible_truncation)]
+pub fn owner_shape_index(user_data: u128) -> usize {
+    (user_data >> 64) as usize
+}
+
+/// Whether two object scales only differ by the rounding errors of decomposing a rotated
+/// transform, in which case the shapes are not rebuilt.
+pub fn scale_approx_eq(a: Vector<f32>, b: Vector<f32>) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| a.approx_eq(b))
+}
+
 pub trait RapierCollisionObject {
     fn rid(&self) -> Rid;
     fn remove_space(&mut self, remove_from_space: bool);
@@ -22,21 +46,20 @@ pub trait RapierCollisionObject {
         let (isometry, scale) = transform.into_ext();
         let shape_instance = RapierShapeInstance::new(shape, isometry, scale, disabled);
         self.shapes_mut().push(shape_instance);
-        self.update_shapes();
+        self.insert_shape_collider(self.shapes().len() - 1);
     }
     fn remove_shape_rid(&mut self, shape_rid: Rid) {
         let rid = self.rid();
-        self.shapes_mut().retain(|s| {
-            s.shape.borrow_mut().remove_owner(rid);
-            s.shape.borrow().rid() != shape_rid
-        });
-        self.update_shapes();
+        for idx in (0..self.shapes().len()).rev() {
+            if self.shapes()[idx].shape.borrow().rid() == shape_rid {
+                self.shapes()[idx].shape.borrow_mut().remove_owner(rid);
+                self.remove_nth_shape(idx);
+            }
+        }
     }
     fn remove_nth_shape(&mut self, idx: usize) -> RapierShapeInstance {
-        let rid = self.rid();
         let shape_inst = self.shapes_mut().remove(idx);
-
-        self.update_shapes();
+        self.remove_shape_collider(idx);
         shape_inst
     }
 
@@ -44,8 +67,9 @@ pub trait RapierCollisionObject {
         for shape in self.shapes() {
             shape.shape.borrow_mut().remove_owner(self.rid());
         }
-        self.shapes_mut().clear();
-        self.update_shapes();
+        for idx in (0..self.shapes().len()).rev() {
+            self.remove_nth_shape(idx);
+        }
     }
 
     fn set_shape(&mut self, idx: usize, s: Rc<RefCell<dyn RapierShape>>) {
@@ -53,7 +77,7 @@ pub trait RapierCollisionObject {
         if let Some(shape) = self.shapes_mut().get_mut(idx) {
             shape.shape.borrow_mut().remove_owner(rid);
             shape.shape = s.clone();
-            self.update_shapes();
+            self.update_shape_collider(idx);
         } else {
             godot_error!("{}", RapierError::ShapeNotInObject(idx, self.rid()));
         }
@@ -72,7 +96,7 @@ pub trait RapierCollisionObject {
             let (isometry, scale) = transform.into_ext();
             shape.isometry = isometry;
             shape.scale = scale;
-            self.update_shapes();
+            self.update_shape_collider(idx);
         } else {
             godot_error!("{}", RapierError::ShapeNotInObject(idx, self.rid()));
         }
@@ -81,7 +105,7 @@ pub trait RapierCollisionObject {
     fn set_shape_disabled(&mut self, idx: usize, disabled: bool) {
         if let Some(shape) = self.shapes_mut().get_mut(idx) {
             shape.disabled = disabled;
-            self.update_shapes();
+            self.update_shape_collider(idx);
         } else {
             godot_error!("{}", RapierError::ShapeNotInObject(idx, self.rid()));
         }
@@ -89,26 +113,33 @@ pub trait RapierCollisionObject {
 
     fn shapes(&self) -> &Vec<RapierShapeInstance>;
     fn shapes_mut(&mut self) -> &mut Vec<RapierShapeInstance>;
-    fn update_shapes(&mut self);
+    /// Creates the collider of the shape that was just inserted at `idx`.
+    fn insert_shape_collider(&mut self, idx: usize);
+    /// Removes the collider of the shape that was just removed from `idx`.
+    fn remove_shape_collider(&mut self, idx: usize);
+    /// Rebuilds the collider of the shape at `idx` after the shape instance changed.
+    fn update_shape_collider(&mut self, idx: usize);
+    fn update_shapes(&mut self) {
+        for idx in 0..self.shapes().len() {
+            self.update_shape_collider(idx);
+        }
+    }
     fn set_instance_id(&mut self, id: u64);
     fn instance_id(&self) -> Option<u64>;
 
-    fn build_shared_shape(&self) -> Option<SharedShape> {
-        if self.shapes().is_empty() {
-            None
-        } else if self.shapes().len() == 1 {
-            let shape_instance = &self.shapes()[0];
-            Some(shape_instance.shared_shape())
+    /// Whether the object is simulated as a dynamic body, which cannot use concave shapes.
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+
+    fn instance_shared_shape(&self, shape_instance: &RapierShapeInstance) -> SharedShape {
+        if self.is_dynamic() {
+            shape_instance.dynamic_shared_shape(self.scale())
         } else {
-            let compound_shapes: Vec<(Isometry<f32>, SharedShape)> = self
-                .shapes()
-                .iter()
-                .filter(|shape_instance| !shape_instance.disabled)
-                .map(|shape_instance| (shape_instance.isometry, shape_instance.shared_shape()))
-                .collect();
-            Some(SharedShape::new(Compound::new(compound_shapes)))
+            shape_instance.shared_shape(self.scale())
         }
     }
+
     fn transform(&self) -> Transform3D;
     fn isometry(&self) -> Isometry<f32> {
         let (iso, _) = self.transform().into_ext();
@@ -119,33 +150,24 @@ pub trait RapierCollisionObject {
         scale
     }
 
-    fn build_collider(&self) -> ColliderBuilder {
+    /// Position of the collider of `shape_instance`, relative to the rigid body it is attached
+    /// to if any.
+    fn collider_position(&self, shape_instance: &RapierShapeInstance) -> Isometry<f32> {
+        self.isometry() * shape_instance.geometry_isometry(self.scale())
+    }
+
+    fn build_collider(&self, idx: usize) -> Collider {
         let collision_groups = InteractionGroups::new(
             Group::from(self.get_collision_layer()),
             Group::from(self.get_collision_mask()),
         );
-        self.build_shared_shape().map_or(
-            {
-                // Empty shape collider
-                ColliderBuilder::ball(0.1)
-                    .enabled(false)
-                    .sensor(true)
-                    .collision_groups(collision_groups)
-            },
-            |shared_shape| {
-                if shared_shape.as_compound().is_some() {
-                    ColliderBuilder::new(shared_shape)
-                        .collision_groups(collision_groups)
-                        .position(self.isometry())
-                } else {
-                    let shape_instance = &self.shapes()[0];
-                    ColliderBuilder::new(shared_shape)
-                        .position(self.isometry() * shape_instance.isometry)
-                        .enabled(!shape_instance.disabled)
-                        .collision_groups(collision_groups)
-                }
-            },
-        )
+        let shape_instance = &self.shapes()[idx];
+        ColliderBuilder::new(self.instance_shared_shape(shape_instance))
+            .position(self.collider_position(shape_instance))
+            .enabled(shape_instance.is_enabled())
+            .collision_groups(collision_groups)
+            .user_data(owner_user_data(self.rid(), idx))
+            .build()
     }
 
     fn set_collision_layer(&mut self, layer: u32);
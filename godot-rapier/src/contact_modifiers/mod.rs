@@ -0,0 +1,7 @@
+pub mod one_way;
+pub mod separation_ray;
+pub mod surface_velocity;
+
+pub use self::one_way::OneWayCollision;
+pub use self::separation_ray::SeparationRay;
+pub use self::surface_velocity::SurfaceVelocity;
@@ -0,0 +1,77 @@
+use rapier3d::prelude::*;
+
+use crate::physics_hooks::ContactModifier;
+
+/// Maximum angle between a contact normal and the one-way direction for the contact to be kept.
+pub const ONE_WAY_ALLOWED_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+
+const CONTACT_CONFIGURATION_UNKNOWN: u32 = 0;
+const CONTACT_CURRENTLY_ALLOWED: u32 = 1;
+const CONTACT_CURRENTLY_FORBIDDEN: u32 = 2;
+const CONTACT_STATE_MASK: u32 = 0b11;
+
+/// Lets other objects pass through a collider from every side but one.
+///
+/// The direction is expressed in the collider local frame.
+pub struct OneWayCollision {
+    direction: Vector<Real>,
+}
+
+impl OneWayCollision {
+    pub const fn new(direction: Vector<Real>) -> Self {
+        Self { direction }
+    }
+}
+
+/// Whether a contact whose outward normal on the one-way shape is `local_normal` is allowed.
+pub fn allows_normal(direction: &Vector<Real>, local_normal: &Vector<Real>) -> bool {
+    local_normal.dot(direction) >= ONE_WAY_ALLOWED_ANGLE.cos()
+}
+
+impl ContactModifier for OneWayCollision {
+    // Same state machine as `ContactModificationContext::update_as_oneway_platform`, but usable
+    // on either side of the manifold: each side keeps its state in its own bits of `user_data`.
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext, is_collider1: bool) {
+        let local_normal = if is_collider1 {
+            context.manifold.local_n1
+        } else {
+            context.manifold.local_n2
+        };
+
+        let shift = if is_collider1 { 0 } else { 2 };
+        let contact_is_ok = allows_normal(&self.direction, &local_normal);
+        let state = match (*context.user_data >> shift) & CONTACT_STATE_MASK {
+            CONTACT_CURRENTLY_FORBIDDEN => {
+                if contact_is_ok && context.solver_contacts.iter().all(|c| c.dist > 0.0) {
+                    CONTACT_CURRENTLY_ALLOWED
+                } else {
+                    context.solver_contacts.clear();
+                    CONTACT_CURRENTLY_FORBIDDEN
+                }
+            }
+            CONTACT_CURRENTLY_ALLOWED => {
+                if context.solver_contacts.is_empty() {
+                    CONTACT_CONFIGURATION_UNKNOWN
+                } else {
+                    CONTACT_CURRENTLY_ALLOWED
+                }
+            }
+            _ => {
+                if contact_is_ok {
+                    CONTACT_CURRENTLY_ALLOWED
+                } else {
+                    context.solver_contacts.clear();
+                    // The normal can be zero when both shapes exactly touch, wait for the next
+                    // step before forbidding further contacts.
+                    if local_normal.norm_squared() > 0.1 {
+                        CONTACT_CURRENTLY_FORBIDDEN
+                    } else {
+                        CONTACT_CONFIGURATION_UNKNOWN
+                    }
+                }
+            }
+        };
+        *context.user_data =
+            (*context.user_data & !(CONTACT_STATE_MASK << shift)) | (state << shift);
+    }
+}
@@ -0,0 +1,72 @@
+use rapier3d::prelude::*;
+
+use crate::physics_hooks::ContactModifier;
+
+/// Below this cosine between the contact normal and the ray, the ray runs along the surface
+/// instead of going through it and the contact is dropped.
+const MIN_RAY_NORMAL_COS: Real = 1.0e-3;
+
+/// Makes a separation ray collider push only along its ray, the local Z axis, unless it slides
+/// on slopes, in which case it keeps the surface normal.
+#[derive(Clone, Copy)]
+pub struct SeparationRay {
+    slide_on_slope: bool,
+}
+
+impl SeparationRay {
+    pub const fn new(slide_on_slope: bool) -> Self {
+        Self { slide_on_slope }
+    }
+
+    /// World direction of a ray whose collider or shape is at `position`.
+    pub fn direction(position: &Isometry<Real>) -> Vector<Real> {
+        position.rotation * Vector::z()
+    }
+
+    /// Turns the `normal` and `distance` of a contact, the normal pointing from the ray into the
+    /// other shape, into the ones of the ray pointing in `direction`. The distance is measured
+    /// along the ray. Returns `None` if the ray cannot be pushed out along itself.
+    pub fn resolve(
+        &self,
+        direction: &Vector<Real>,
+        normal: &Vector<Real>,
+        distance: Real,
+    ) -> Option<(Vector<Real>, Real)> {
+        if self.slide_on_slope {
+            return Some((*normal, distance));
+        }
+        let cos = direction.dot(normal);
+        if cos < MIN_RAY_NORMAL_COS {
+            return None;
+        }
+        Some((*direction, distance / cos))
+    }
+}
+
+impl ContactModifier for SeparationRay {
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext, is_collider1: bool) {
+        if self.slide_on_slope {
+            return;
+        }
+        let handle = if is_collider1 {
+            context.collider1
+        } else {
+            context.collider2
+        };
+        let Some(collider) = context.colliders.get(handle) else {
+            return;
+        };
+        // The manifold normal points from collider1 to collider2.
+        let sign = if is_collider1 { 1.0 } else { -1.0 };
+        let direction = Self::direction(collider.position());
+        let cos = direction.dot(&(*context.normal * sign));
+        if cos < MIN_RAY_NORMAL_COS {
+            context.solver_contacts.clear();
+            return;
+        }
+        for solver_contact in context.solver_contacts.iter_mut() {
+            solver_contact.dist /= cos;
+        }
+        *context.normal = direction * sign;
+    }
+}
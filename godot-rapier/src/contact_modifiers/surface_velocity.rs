@@ -0,0 +1,50 @@
+use rapier3d::prelude::*;
+
+use crate::physics_hooks::ContactModifier;
+
+/// Moves objects touching a collider as if its surface had a velocity, without moving the
+/// collider itself, like a conveyor belt or a rotating platform.
+///
+/// Velocities are in world space, the angular velocity turns around the center of mass of the
+/// collider parent.
+pub struct SurfaceVelocity {
+    linear_velocity: Vector<Real>,
+    angular_velocity: Vector<Real>,
+}
+
+impl SurfaceVelocity {
+    pub const fn new(linear_velocity: Vector<Real>, angular_velocity: Vector<Real>) -> Self {
+        Self {
+            linear_velocity,
+            angular_velocity,
+        }
+    }
+
+    pub const fn angular_velocity(&self) -> Vector<Real> {
+        self.angular_velocity
+    }
+
+    pub fn velocity_at_point(&self, center: &Point<Real>, point: &Point<Real>) -> Vector<Real> {
+        self.linear_velocity + self.angular_velocity.cross(&(point - center))
+    }
+}
+
+impl ContactModifier for SurfaceVelocity {
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext, is_collider1: bool) {
+        let rigid_body = if is_collider1 {
+            context.rigid_body1
+        } else {
+            context.rigid_body2
+        };
+        let center = rigid_body
+            .and_then(|handle| context.bodies.get(handle))
+            .map_or_else(Point::origin, |body| *body.center_of_mass());
+
+        // The solver drives `vel1 - vel2 + tangent_velocity` to zero along the contact tangents.
+        let sign = if is_collider1 { 1.0 } else { -1.0 };
+        for solver_contact in context.solver_contacts.iter_mut() {
+            solver_contact.tangent_velocity +=
+                self.velocity_at_point(&center, &solver_contact.point) * sign;
+        }
+    }
+}
@@ -80,16 +80,32 @@ impl FromExt<Isometry<f32>> for Transform3D {
             isometry.translation.y,
             isometry.translation.z,
         );
-        let rapier_quat = isometry.rotation.coords;
-        let godot_quat =
-            Quaternion::new(rapier_quat.x, rapier_quat.y, rapier_quat.z, rapier_quat.w);
         Self {
-            basis: Basis::from_quat(godot_quat),
+            basis: isometry.rotation.into_ext(),
             origin,
         }
     }
 }
 
+impl FromExt<Rotation<f32>> for Basis {
+    fn from_ext(rotation: Rotation<f32>) -> Self {
+        let rapier_quat = rotation.coords;
+        let godot_quat =
+            Quaternion::new(rapier_quat.x, rapier_quat.y, rapier_quat.z, rapier_quat.w);
+        Self::from_quat(godot_quat)
+    }
+}
+
+impl FromExt<Matrix<f32>> for Basis {
+    fn from_ext(matrix: Matrix<f32>) -> Self {
+        Self::from_rows(
+            Vector3::new(matrix.m11, matrix.m12, matrix.m13),
+            Vector3::new(matrix.m21, matrix.m22, matrix.m23),
+            Vector3::new(matrix.m31, matrix.m32, matrix.m33),
+        )
+    }
+}
+
 impl FromExt<BodyMode> for RigidBodyType {
     #[inline]
     fn from_ext(mode: BodyMode) -> Self {
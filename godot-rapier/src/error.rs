@@ -25,6 +25,8 @@ pub enum RapierError {
     BodyInstanceIDNotSet(Rid),
     #[error("Shape with index {0} isn't present in object with {1}")]
     ShapeNotInObject(usize, Rid),
+    #[error("Invalid custom shape data: {0}")]
+    InvalidCustomShapeData(String),
     #[error("Object with {0} is being build without shape (will use a disabled sphere as a stub)")]
     BuildingObjectWithNoShapes(Rid),
 }
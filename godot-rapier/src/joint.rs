@@ -2,10 +2,37 @@ use godot::prelude::Rid;
 
 pub struct RapierJoint {
     rid: Rid,
+    bodies: Option<(Rid, Rid)>,
+    disabled_collisions_between_bodies: bool,
 }
 
 impl RapierJoint {
     pub const fn new(rid: Rid) -> Self {
-        Self { rid }
+        Self {
+            rid,
+            bodies: None,
+            disabled_collisions_between_bodies: true,
+        }
+    }
+
+    pub const fn rid(&self) -> Rid {
+        self.rid
+    }
+
+    /// The two bodies the joint connects, once it was made.
+    pub const fn bodies(&self) -> Option<(Rid, Rid)> {
+        self.bodies
+    }
+
+    pub fn set_bodies(&mut self, body_a: Rid, body_b: Rid) {
+        self.bodies = Some((body_a, body_b));
+    }
+
+    pub const fn is_disabled_collisions_between_bodies(&self) -> bool {
+        self.disabled_collisions_between_bodies
+    }
+
+    pub fn set_disabled_collisions_between_bodies(&mut self, disabled: bool) {
+        self.disabled_collisions_between_bodies = disabled;
     }
 }
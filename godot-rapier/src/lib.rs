@@ -10,11 +10,13 @@ struct RapierPhysics;
 mod area;
 mod body;
 mod collision_object;
+mod contact_modifiers;
 mod conversions;
 mod direct_body_state_3d;
 mod direct_space_state_3d;
 mod error;
 mod joint;
+mod physics_hooks;
 mod physics_server_3d;
 mod physics_server_3d_utils;
 mod shapes;
@@ -0,0 +1,284 @@
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+};
+
+use godot::prelude::Rid;
+use rapier3d::prelude::*;
+
+use crate::collision_object::owner_rid;
+
+/// Custom rule deciding whether two colliders are allowed to interact.
+pub trait PairFilter: Send + Sync {
+    fn allows_pair(&self, context: &PairFilterContext) -> bool;
+}
+
+/// Edits the solver contacts of every manifold involving the collider it is attached to.
+pub trait ContactModifier: Send + Sync + 'static {
+    /// `is_collider1` tells whether the modifier's collider is `context.collider1`.
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext, is_collider1: bool);
+}
+
+type ContactModifiers = Vec<(TypeId, Box<dyn ContactModifier>)>;
+
+/// Why two owners must not collide. A pair stays excluded while any of its reasons remains.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Exclusion {
+    /// A collision exception added by this owner.
+    Exception(Rid),
+    /// A joint between the owners with collisions between its bodies disabled.
+    Joint(Rid),
+}
+
+/// Key of a pair of owners, the same whatever their order.
+fn pair_key(a: Rid, b: Rid) -> (Rid, Rid) {
+    if a.to_u64() <= b.to_u64() {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[derive(Default)]
+pub struct RapierPhysicsHooks {
+    excluded_pairs: HashMap<(Rid, Rid), HashSet<Exclusion>>,
+    /// The owners each owner has excluded pairs with, so that finding whether an owner needs
+    /// pair filtering doesn't go through every pair.
+    excluded_partners: HashMap<Rid, HashSet<Rid>>,
+    pair_filters: Vec<Box<dyn PairFilter>>,
+    contact_modifiers: HashMap<ColliderHandle, ContactModifiers>,
+}
+
+impl RapierPhysicsHooks {
+    fn add_exclusion(&mut self, a: Rid, b: Rid, exclusion: Exclusion) {
+        self.excluded_pairs
+            .entry(pair_key(a, b))
+            .or_default()
+            .insert(exclusion);
+        self.excluded_partners.entry(a).or_default().insert(b);
+        self.excluded_partners.entry(b).or_default().insert(a);
+    }
+
+    fn remove_exclusion(&mut self, a: Rid, b: Rid, exclusion: Exclusion) {
+        let key = pair_key(a, b);
+        let Some(exclusions) = self.excluded_pairs.get_mut(&key) else {
+            return;
+        };
+        exclusions.remove(&exclusion);
+        if !exclusions.is_empty() {
+            return;
+        }
+        self.excluded_pairs.remove(&key);
+        for (owner, partner) in [(a, b), (b, a)] {
+            if let Some(partners) = self.excluded_partners.get_mut(&owner) {
+                partners.remove(&partner);
+                if partners.is_empty() {
+                    self.excluded_partners.remove(&owner);
+                }
+            }
+        }
+    }
+
+    /// Adds the collision exception of `owner` with `other`.
+    pub fn exclude_pair(&mut self, owner: Rid, other: Rid) {
+        self.add_exclusion(owner, other, Exclusion::Exception(owner));
+    }
+
+    /// Removes the collision exception of `owner` with `other`, the pair still doesn't collide
+    /// if `other` excepted `owner` too or a joint disables it.
+    pub fn include_pair(&mut self, owner: Rid, other: Rid) {
+        self.remove_exclusion(owner, other, Exclusion::Exception(owner));
+    }
+
+    /// Disables collisions between the bodies of `joint` until `include_joint_pair`.
+    pub fn exclude_joint_pair(&mut self, joint: Rid, a: Rid, b: Rid) {
+        self.add_exclusion(a, b, Exclusion::Joint(joint));
+    }
+
+    pub fn include_joint_pair(&mut self, joint: Rid, a: Rid, b: Rid) {
+        self.remove_exclusion(a, b, Exclusion::Joint(joint));
+    }
+
+    /// Removes the collision exceptions `owner` added, returns the owners whose pairs with it
+    /// may have changed.
+    pub fn clear_exclusions(&mut self, owner: Rid) -> Vec<Rid> {
+        let partners: Vec<Rid> = self
+            .excluded_partners
+            .get(&owner)
+            .map(|partners| partners.iter().copied().collect())
+            .unwrap_or_default();
+        for other in &partners {
+            self.include_pair(owner, *other);
+        }
+        partners
+    }
+
+    pub fn is_pair_excluded(&self, a: Rid, b: Rid) -> bool {
+        self.excluded_pairs.contains_key(&pair_key(a, b))
+    }
+
+    pub fn add_pair_filter(&mut self, filter: Box<dyn PairFilter>) {
+        self.pair_filters.push(filter);
+    }
+
+    /// Attaches `modifier` to the collider, replacing any previous modifier of the same type.
+    pub fn set_contact_modifier<M: ContactModifier>(
+        &mut self,
+        handle: ColliderHandle,
+        modifier: M,
+    ) {
+        let modifiers = self.contact_modifiers.entry(handle).or_default();
+        let type_id = TypeId::of::<M>();
+        if let Some(entry) = modifiers.iter_mut().find(|(id, _)| *id == type_id) {
+            entry.1 = Box::new(modifier);
+        } else {
+            modifiers.push((type_id, Box::new(modifier)));
+        }
+    }
+
+    pub fn remove_contact_modifier<M: ContactModifier>(&mut self, handle: ColliderHandle) {
+        if let Some(modifiers) = self.contact_modifiers.get_mut(&handle) {
+            modifiers.retain(|(id, _)| *id != TypeId::of::<M>());
+            if modifiers.is_empty() {
+                self.contact_modifiers.remove(&handle);
+            }
+        }
+    }
+
+    pub fn remove_collider(&mut self, handle: ColliderHandle) {
+        self.contact_modifiers.remove(&handle);
+    }
+
+    /// Hooks a collider has to opt in to so that its pairs go through this struct. Solver
+    /// contacts are always modified, to combine the materials like Godot.
+    pub fn active_hooks(&self, handle: ColliderHandle, owner: Rid) -> ActiveHooks {
+        let mut active_hooks = ActiveHooks::MODIFY_SOLVER_CONTACTS;
+        if !self.pair_filters.is_empty() || self.excluded_partners.contains_key(&owner) {
+            active_hooks |=
+                ActiveHooks::FILTER_CONTACT_PAIRS | ActiveHooks::FILTER_INTERSECTION_PAIR;
+        }
+        active_hooks
+    }
+
+    fn allows_pair(&self, context: &PairFilterContext) -> bool {
+        if let (Some(collider1), Some(collider2)) = (
+            context.colliders.get(context.collider1),
+            context.colliders.get(context.collider2),
+        ) {
+            if self.is_pair_excluded(
+                owner_rid(collider1.user_data),
+                owner_rid(collider2.user_data),
+            ) {
+                return false;
+            }
+        }
+        self.pair_filters
+            .iter()
+            .all(|filter| filter.allows_pair(context))
+    }
+}
+
+/// Godot's combined bounce of two bodies, the bounce of an absorbent material is negative.
+fn combine_bounce(bounce1: Real, bounce2: Real) -> Real {
+    (bounce1 + bounce2).clamp(0.0, 1.0)
+}
+
+/// Godot's combined friction of two bodies, the friction of a rough material is negative.
+fn combine_friction(friction1: Real, friction2: Real) -> Real {
+    friction1.min(friction2).abs()
+}
+
+/// Replaces the coefficients Rapier combined with the ones Godot would use, none of Rapier's
+/// combine rules adds bounces up or lets a rough material win.
+fn combine_materials(context: &mut ContactModificationContext) {
+    let (Some(collider1), Some(collider2)) = (
+        context.colliders.get(context.collider1),
+        context.colliders.get(context.collider2),
+    ) else {
+        return;
+    };
+    let restitution = combine_bounce(collider1.restitution(), collider2.restitution());
+    let friction = combine_friction(collider1.friction(), collider2.friction());
+    for solver_contact in context.solver_contacts.iter_mut() {
+        solver_contact.restitution = restitution;
+        solver_contact.friction = friction;
+    }
+}
+
+impl PhysicsHooks for RapierPhysicsHooks {
+    fn filter_contact_pair(&self, context: &PairFilterContext) -> Option<SolverFlags> {
+        if self.allows_pair(context) {
+            Some(SolverFlags::COMPUTE_IMPULSES)
+        } else {
+            None
+        }
+    }
+
+    fn filter_intersection_pair(&self, context: &PairFilterContext) -> bool {
+        self.allows_pair(context)
+    }
+
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext) {
+        combine_materials(context);
+        if let Some(modifiers) = self.contact_modifiers.get(&context.collider1) {
+            for (_, modifier) in modifiers {
+                modifier.modify_solver_contacts(context, true);
+            }
+        }
+        if let Some(modifiers) = self.contact_modifiers.get(&context.collider2) {
+            for (_, modifier) in modifiers {
+                modifier.modify_solver_contacts(context, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pair_stays_excluded_while_a_reason_remains() {
+        let (a, b, joint) = (Rid::new(1), Rid::new(2), Rid::new(3));
+        let mut hooks = RapierPhysicsHooks::default();
+        hooks.exclude_pair(a, b);
+        hooks.exclude_joint_pair(joint, b, a);
+        assert!(hooks.is_pair_excluded(b, a));
+
+        hooks.include_pair(a, b);
+        assert!(hooks.is_pair_excluded(a, b));
+        hooks.include_joint_pair(joint, a, b);
+        assert!(!hooks.is_pair_excluded(a, b));
+    }
+
+    #[test]
+    fn only_owners_with_exclusions_filter_pairs() {
+        let (a, b, c) = (Rid::new(1), Rid::new(2), Rid::new(3));
+        let handle = ColliderHandle::from_raw_parts(0, 0);
+        let mut hooks = RapierPhysicsHooks::default();
+        hooks.exclude_pair(a, b);
+        assert!(hooks
+            .active_hooks(handle, b)
+            .contains(ActiveHooks::FILTER_CONTACT_PAIRS));
+        let pair_hooks = ActiveHooks::FILTER_CONTACT_PAIRS | ActiveHooks::FILTER_INTERSECTION_PAIR;
+        assert!(!hooks.active_hooks(handle, c).intersects(pair_hooks));
+
+        assert_eq!(hooks.clear_exclusions(a), vec![b]);
+        assert!(!hooks.active_hooks(handle, a).intersects(pair_hooks));
+        assert!(!hooks.active_hooks(handle, b).intersects(pair_hooks));
+    }
+
+    #[test]
+    fn materials_combine_like_godot() {
+        assert_eq!(combine_bounce(0.25, 0.5), 0.75);
+        assert_eq!(combine_bounce(0.75, 0.5), 1.0);
+        // An absorbent material takes bounce away from the other one.
+        assert_eq!(combine_bounce(-0.25, 0.5), 0.25);
+        assert_eq!(combine_bounce(-0.75, 0.5), 0.0);
+
+        assert_eq!(combine_friction(0.25, 0.5), 0.25);
+        // A rough material wins over the other one.
+        assert_eq!(combine_friction(-0.75, 0.5), 0.75);
+        assert_eq!(combine_friction(-0.25, 0.5), 0.25);
+    }
+}
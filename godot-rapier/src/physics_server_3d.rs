@@ -13,7 +13,7 @@ use std::f32::consts::E;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use godot::engine::native::PhysicsServer3DExtensionMotionResult;
+use godot::engine::native::{ObjectId, PhysicsServer3DExtensionMotionResult};
 use godot::engine::physics_server_3d::{BodyMode, SpaceParameter};
 use godot::engine::{Engine, PhysicsServer3DExtensionVirtual};
 use godot::prelude::utilities::{rid_allocate_id, rid_from_int64};
@@ -30,10 +30,11 @@ use crate::direct_space_state_3d::RapierPhysicsDirectSpaceState3D;
 use crate::error::{RapierError, RapierResult};
 use crate::joint::RapierJoint;
 use crate::physics_server_3d_utils::make_rid;
+use crate::shapes::convex_decomposition::take_completed_decompositions;
 use crate::shapes::{
-    RapierBoxShape, RapierCapsuleShape, RapierConcaveShape, RapierConvexShape, RapierCylinderShape,
-    RapierHeightmapShape, RapierSeparationRayShape, RapierShape, RapierSphereShape,
-    RapierWorldBoundaryShape,
+    RapierBoxShape, RapierCapsuleShape, RapierConcaveShape, RapierConvexShape, RapierCustomShape,
+    RapierCylinderShape, RapierHeightmapShape, RapierSeparationRayShape, RapierShape,
+    RapierSphereShape, RapierWorldBoundaryShape,
 };
 use crate::space::RapierSpace;
 
@@ -54,6 +55,39 @@ pub struct RapierPhysicsServer3D {
     flushing_queries: bool,
 }
 
+/// Size of the collision array in `PhysicsServer3DExtensionMotionResult`.
+const MAX_MOTION_COLLISIONS: i32 = 32;
+
+#[godot_api]
+impl RapierPhysicsServer3D {
+    /// Makes a body shape only collide with contacts coming against `direction`, in shape local
+    /// space, like a one-way platform.
+    #[func]
+    fn body_set_shape_as_one_way_collision(
+        &mut self,
+        body: Rid,
+        shape_idx: i32,
+        enable: bool,
+        direction: Vector3,
+    ) {
+        if let Ok(body) = self.get_body(body) {
+            body.borrow_mut()
+                .set_shape_one_way_direction(shape_idx as usize, enable.then_some(direction));
+        }
+    }
+
+    #[func]
+    fn body_is_shape_set_as_one_way_collision(&self, body: Rid, shape_idx: i32) -> bool {
+        if let Ok(body) = self.get_body(body) {
+            return body
+                .borrow()
+                .shape_one_way_direction(shape_idx as usize)
+                .is_some();
+        }
+        false
+    }
+}
+
 #[godot_api]
 impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
     fn world_boundary_shape_create(&mut self) -> Rid {
@@ -128,13 +162,17 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         rid
     }
     fn custom_shape_create(&mut self) -> Rid {
-        godot_warn!("Godot Rapier doesn't support custom shapes");
-        Rid::Invalid
+        let rid = make_rid();
+        let shape = RapierCustomShape::new(rid);
+        self.shapes.insert(rid, Rc::new(RefCell::new(shape)));
+        godot_print!("created shape at {rid}");
+        rid
     }
 
     fn shape_set_data(&mut self, shape: Rid, data: Variant) {
         if let Ok(shape) = self.get_shape(shape) {
             shape.borrow_mut().set_data(data);
+            shape.borrow().update_owners_if_invalidated();
         }
     }
     fn shape_set_custom_solver_bias(&mut self, shape: Rid, bias: f32) {
@@ -145,6 +183,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
     fn shape_set_margin(&mut self, shape: Rid, margin: f32) {
         if let Ok(shape) = self.get_shape(shape) {
             shape.borrow_mut().set_margin(margin);
+            shape.borrow().update_owners_if_invalidated();
         }
     }
     fn shape_get_margin(&self, shape: Rid) -> f32 {
@@ -178,8 +217,8 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         let default_area = Rc::new(RefCell::new(RapierArea::new(space_rid)));
         self.areas.insert(space_rid, default_area.clone());
 
-        let handle = space.borrow_mut().set_default_area(default_area.clone());
-        default_area.borrow_mut().set_space(space.clone(), handle);
+        space.borrow_mut().set_default_area(default_area.clone());
+        default_area.borrow_mut().set_space(space.clone());
 
         space.borrow_mut().set_direct_state(Rc::downgrade(&space));
         self.spaces.insert(space_rid, space);
@@ -245,8 +284,8 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
             if space_rid.is_valid() {
                 if let Ok(space) = self.get_space(space_rid) {
                     area.borrow_mut().remove_space(true);
-                    let handle = space.borrow_mut().add_area(area);
-                    area.borrow_mut().set_space(space.clone(), handle);
+                    space.borrow_mut().add_area(area);
+                    area.borrow_mut().set_space(space.clone());
                     godot_print!("set space {space_rid} to area {area_rid}");
                 }
             }
@@ -712,13 +751,24 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         false
     }
     fn body_add_collision_exception(&mut self, body: Rid, excepted_body: Rid) {
-        //TODO
+        if let Ok(body) = self.get_body(body) {
+            body.borrow_mut().add_collision_exception(excepted_body);
+        }
     }
     fn body_remove_collision_exception(&mut self, body: Rid, excepted_body: Rid) {
-        //TODO
+        if let Ok(body) = self.get_body(body) {
+            body.borrow_mut().remove_collision_exception(excepted_body);
+        }
     }
     fn body_get_collision_exceptions(&self, body: Rid) -> Array<Rid> {
-        //TODO
+        if let Ok(body) = self.get_body(body) {
+            return body
+                .borrow()
+                .collision_exceptions()
+                .iter()
+                .copied()
+                .collect();
+        }
         Array::new()
     }
     fn body_set_max_contacts_reported(&mut self, body: Rid, amount: i32) {
@@ -779,8 +829,52 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         recovery_as_collision: bool,
         result: *mut PhysicsServer3DExtensionMotionResult,
     ) -> bool {
-        //TODO
-        false
+        let Ok(body) = self.get_body(body) else {
+            return false;
+        };
+        let body = body.borrow();
+        let Some(space_info) = body.space_info() else {
+            godot_error!("{}", RapierError::BodySpaceNotSet(body.rid()));
+            return false;
+        };
+        let (from, _): (Isometry<f32>, Vector<f32>) = from.into_ext();
+        let motion_result = space_info.space.borrow().body_test_motion(
+            &body,
+            from,
+            motion.into_ext(),
+            margin,
+            max_collisions.clamp(0, MAX_MOTION_COLLISIONS) as usize,
+            collide_separation_ray,
+            recovery_as_collision,
+        );
+
+        if let Some(result) = result.as_mut() {
+            result.travel = motion_result.travel.into_ext();
+            result.remainder = motion_result.remainder.into_ext();
+            result.collision_depth = motion_result
+                .collisions
+                .first()
+                .map_or(0.0, |collision| collision.depth);
+            result.collision_safe_fraction = motion_result.collision_safe_fraction;
+            result.collision_unsafe_fraction = motion_result.collision_unsafe_fraction;
+            for (i, collision) in motion_result.collisions.iter().enumerate() {
+                let result_collision = &mut result.collisions[i];
+                result_collision.position = collision.position.into_ext();
+                result_collision.normal = collision.normal.into_ext();
+                result_collision.collider_velocity = collision.collider_velocity.into_ext();
+                result_collision.collider_angular_velocity =
+                    collision.collider_angular_velocity.into_ext();
+                result_collision.depth = collision.depth;
+                result_collision.local_shape = collision.local_shape as i32;
+                result_collision.collider_id = ObjectId {
+                    id: collision.collider_id,
+                };
+                result_collision.collider = collision.collider;
+                result_collision.collider_shape = collision.collider_shape as i32;
+            }
+            result.collision_count = motion_result.collisions.len() as i32;
+        }
+        !motion_result.collisions.is_empty()
     }
     fn body_get_direct_state(
         &mut self,
@@ -932,7 +1026,11 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
     }
     fn joint_clear(&mut self, joint_id: Rid) {
         if let Ok(joint) = self.get_joint(joint_id) {
-            let empty_joint = RapierJoint::new(joint_id);
+            self.set_joint_collisions_disabled(&joint.borrow(), false);
+            let mut empty_joint = RapierJoint::new(joint_id);
+            empty_joint.set_disabled_collisions_between_bodies(
+                joint.borrow().is_disabled_collisions_between_bodies(),
+            );
             joint.replace(empty_joint);
         }
     }
@@ -944,6 +1042,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         body_B: Rid,
         local_B: Vector3,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn pin_joint_set_param(
@@ -983,6 +1082,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         body_B: Rid,
         hinge_B: Transform3D,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn joint_make_hinge_simple(
@@ -995,6 +1095,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         pivot_B: Vector3,
         axis_B: Vector3,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn hinge_joint_set_param(
@@ -1037,6 +1138,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         body_B: Rid,
         local_ref_B: Transform3D,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn slider_joint_set_param(
@@ -1063,6 +1165,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         body_B: Rid,
         local_ref_B: Transform3D,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn cone_twist_joint_set_param(
@@ -1089,6 +1192,7 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         body_B: Rid,
         local_ref_B: Transform3D,
     ) {
+        self.make_joint(joint, body_A, body_B);
         // TODO
     }
     fn generic_6dof_joint_set_param(
@@ -1138,10 +1242,18 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         0
     }
     fn joint_disable_collisions_between_bodies(&mut self, joint: Rid, disable: bool) {
-        // TODO
+        if let Ok(joint) = self.get_joint(joint) {
+            let mut joint = joint.borrow_mut();
+            if joint.is_disabled_collisions_between_bodies() != disable {
+                joint.set_disabled_collisions_between_bodies(disable);
+                self.set_joint_collisions_disabled(&joint, disable);
+            }
+        }
     }
     fn joint_is_disabled_collisions_between_bodies(&self, joint: Rid) -> bool {
-        // TODO
+        if let Ok(joint) = self.get_joint(joint) {
+            return joint.borrow().is_disabled_collisions_between_bodies();
+        }
         false
     }
     fn free_rid(&mut self, rid: Rid) {
@@ -1154,8 +1266,8 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
         } else if let Some(space) = self.spaces.remove(&rid) {
             space.borrow_mut().remove_space_from_bodies_areas();
             self.active_spaces.remove(&rid);
-        } else if self.joints.contains_key(&rid) {
-            self.joints.remove(&rid);
+        } else if let Some(joint) = self.joints.remove(&rid) {
+            self.set_joint_collisions_disabled(&joint.borrow(), false);
         } else {
             godot_error!("Failed to free RID: The specified {} has no owner.", rid);
         }
@@ -1169,9 +1281,14 @@ impl PhysicsServer3DExtensionVirtual for RapierPhysicsServer3D {
             return;
         }
 
+        if take_completed_decompositions() {
+            for shape in self.shapes.values() {
+                shape.borrow().poll_background_work();
+            }
+        }
         for space in &self.active_spaces {
             if let Ok(space) = self.get_space(*space) {
-                space.borrow_mut().step();
+                RapierSpace::step(space);
             };
         }
     }
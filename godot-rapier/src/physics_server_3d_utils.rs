@@ -78,4 +78,37 @@ impl RapierPhysicsServer3D {
         godot_error!("{}", RapierError::JointRidMissing(rid));
         Err(RapierError::JointRidMissing(rid))
     }
+
+    /// Excludes, or includes again, the pair of bodies of `joint` in the space of its first body.
+    pub(crate) fn set_joint_collisions_disabled(&self, joint: &RapierJoint, disabled: bool) {
+        let Some((body_a, body_b)) = joint.bodies() else {
+            return;
+        };
+        let Some(space) = self.bodies.get(&body_a).and_then(|body| {
+            body.borrow()
+                .space_info()
+                .map(|space_info| space_info.space.clone())
+        }) else {
+            return;
+        };
+        space
+            .borrow_mut()
+            .set_joint_collisions_disabled(joint.rid(), body_a, body_b, disabled);
+    }
+
+    /// Connects `joint` to its bodies, moving the collision exclusion of its previous bodies.
+    pub(crate) fn make_joint(&self, rid: Rid, body_a: Rid, body_b: Rid) {
+        let Ok(joint) = self.get_joint(rid) else {
+            return;
+        };
+        let mut joint = joint.borrow_mut();
+        let disabled = joint.is_disabled_collisions_between_bodies();
+        if disabled {
+            self.set_joint_collisions_disabled(&joint, false);
+        }
+        joint.set_bodies(body_a, body_b);
+        if disabled {
+            self.set_joint_collisions_disabled(&joint, true);
+        }
+    }
 }
@@ -5,10 +5,11 @@ use rapier3d::prelude::*;
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{rounded_cuboid, RapierShape, SharedShapeCache};
 pub struct RapierBoxShape {
     shape: Cuboid,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
     margin: f32,
 }
@@ -18,6 +19,7 @@ impl RapierBoxShape {
         Self {
             shape: Cuboid::new(vector![0.5, 0.5, 0.5]),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
             margin: 0.0,
         }
@@ -39,21 +41,14 @@ impl RapierShape for RapierBoxShape {
                 let new_half_extents = vector![half_extents.x, half_extents.y, half_extents.z];
                 if new_half_extents != self.shape.half_extents {
                     self.shape.half_extents = new_half_extents;
-                    self.update_owners();
+                    self.shared_shapes.invalidate();
                 }
             }
             Err(err) => godot_error!("{:?}", err),
         };
     }
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
-        if self.margin.is_zero_approx() {
-            SharedShape::new(self.shape.scaled(&scale))
-        } else {
-            SharedShape::new(RoundCuboid {
-                inner_shape: self.shape.scaled(&scale),
-                border_radius: self.margin,
-            })
-        }
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        rounded_cuboid(self.shape.scaled(&scale), self.margin)
     }
 
     fn rid(&self) -> Rid {
@@ -65,14 +60,17 @@ impl RapierShape for RapierBoxShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_BOX
     }
 
     fn set_margin(&mut self, margin: f32) {
-        if margin.approx_eq(&self.margin) {
+        if !margin.approx_eq(&self.margin) {
             self.margin = margin;
-            self.update_owners();
+            self.shared_shapes.invalidate();
         }
     }
 
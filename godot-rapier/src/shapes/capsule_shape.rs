@@ -5,12 +5,19 @@ use rapier3d::{parry::either::Either, prelude::*};
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{
+    data_subdivisions, round_shape_scale, rounded_convex_polyhedron, RapierShape, SharedShapeCache,
+    DEFAULT_SCALED_SUBDIVISIONS,
+};
 
 pub struct RapierCapsuleShape {
     shape: Capsule,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
+    /// Subdivisions of the convex approximation used under non-uniform scale.
+    subdivisions: u32,
+    margin: f32,
 }
 
 impl RapierCapsuleShape {
@@ -18,24 +25,37 @@ impl RapierCapsuleShape {
         Self {
             shape: Capsule::new_y(0.5, 0.2),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
+            subdivisions: DEFAULT_SCALED_SUBDIVISIONS,
+            margin: 0.0,
         }
     }
 }
 
 impl RapierShape for RapierCapsuleShape {
     fn data(&self) -> Variant {
-        Variant::from(dict! {"radius": self.shape.radius,"height":self.shape.height()})
+        let mut data = dict! {"radius": self.shape.radius,"height":self.shape.height()};
+        if self.subdivisions != DEFAULT_SCALED_SUBDIVISIONS {
+            data.set("subdivisions", self.subdivisions);
+        }
+        Variant::from(data)
     }
 
     fn set_data(&mut self, data: Variant) {
         match data.try_to::<Dictionary>() {
             Ok(d) => {
+                if let Some(subdivisions) = data_subdivisions(&d) {
+                    if subdivisions != self.subdivisions {
+                        self.subdivisions = subdivisions;
+                        self.shared_shapes.invalidate();
+                    }
+                }
                 match d.get_or_nil("radius").try_to::<f32>() {
                     Ok(radius) => {
                         if !radius.approx_eq(&self.shape.radius) {
                             self.shape.radius = radius;
-                            self.update_owners();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -44,7 +64,7 @@ impl RapierShape for RapierCapsuleShape {
                     Ok(height) => {
                         if !height.approx_eq(&self.shape.height()) {
                             self.shape.segment.b = self.shape.segment.a + Vector::y() * height;
-                            self.update_owners();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -53,17 +73,21 @@ impl RapierShape for RapierCapsuleShape {
             Err(e) => godot_error!("{:?}", e),
         };
     }
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
-        self.shape.scaled(&scale, 3).map_or_else(
-            || {
-                godot_error!("Scaling one of the collision shape axis to 0");
-                SharedShape::new(self.shape)
-            },
-            |scaled_shape| match scaled_shape {
-                Either::Left(capsule) => SharedShape::new(capsule),
-                Either::Right(convex_poly) => SharedShape::new(convex_poly),
-            },
-        )
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shape
+            .scaled(&round_shape_scale(scale), self.subdivisions)
+            .map_or_else(
+                || {
+                    godot_error!("Scaling one of the collision shape axis to 0");
+                    SharedShape::new(self.shape)
+                },
+                |scaled_shape| match scaled_shape {
+                    Either::Left(capsule) => SharedShape::new(capsule),
+                    Either::Right(convex_poly) => {
+                        rounded_convex_polyhedron(convex_poly, self.margin)
+                    }
+                },
+            )
     }
 
     fn rid(&self) -> Rid {
@@ -75,7 +99,23 @@ impl RapierShape for RapierCapsuleShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_CAPSULE
     }
+
+    /// Capsules keep their exact shape, the margin applies to the convex polyhedron used for
+    /// non-uniform scales.
+    fn set_margin(&mut self, margin: f32) {
+        if !margin.approx_eq(&self.margin) {
+            self.margin = margin;
+            self.shared_shapes.invalidate();
+        }
+    }
+
+    fn margin(&self) -> f32 {
+        self.margin
+    }
 }
@@ -1,40 +1,106 @@
 #![allow(clippy::module_name_repetitions)]
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use godot::prelude::{math::ApproxEq, *};
 use rapier3d::prelude::*;
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{
+    convex_decomposition::{
+        mesh_hash, scaled_decomposition, ConvexDecompositionParams, Decomposition,
+        DecompositionUser,
+    },
+    RapierShape, SharedShapeCache,
+};
 pub struct RapierConcaveShape {
     shape: TriMesh,
+    mesh_hash: u64,
+    faces: Vec<Point<f32>>,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
     backface_collision: bool,
+    decomposition_params: ConvexDecompositionParams,
+    /// The decomposition of the current mesh, requested when a dynamic owner first needs it.
+    decomposition: RefCell<Option<DecompositionUser>>,
+    /// Set while dynamic owners use a placeholder until the decomposition finishes.
+    pending_decomposition: Cell<bool>,
+}
+
+/// Stands in for the mesh while there are no faces. Parry meshes need a triangle, the colliders
+/// of an empty shape are disabled instead.
+fn placeholder_trimesh() -> TriMesh {
+    TriMesh::new(
+        vec![
+            point![0.0, 0.0, 0.0],
+            point![0.0, 0.0, 0.0],
+            point![0.0, 0.0, 0.0],
+        ],
+        vec![[0, 1, 2]],
+    )
 }
 
 impl RapierConcaveShape {
     pub fn new(rid: Rid) -> Self {
+        let shape = placeholder_trimesh();
         Self {
-            shape: TriMesh::new(
-                vec![
-                    point![0.0, 0.0, 0.0],
-                    point![0.0, 0.0, 0.0],
-                    point![0.0, 0.0, 0.0],
-                ],
-                vec![[0, 1, 2]],
-            ),
+            mesh_hash: mesh_hash(shape.vertices(), shape.indices()),
+            shape,
+            faces: vec![],
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
             backface_collision: false,
+            decomposition_params: ConvexDecompositionParams::default(),
+            decomposition: RefCell::new(None),
+            pending_decomposition: Cell::new(false),
         }
     }
 
+    fn decomposition(&self) -> Decomposition {
+        self.decomposition
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                DecompositionUser::new(
+                    self.mesh_hash,
+                    self.shape.vertices(),
+                    self.shape.indices(),
+                    self.decomposition_params,
+                )
+            })
+            .decomposition()
+    }
+
+    /// Convex decomposition of the mesh at `scale`. The decomposition is computed in the
+    /// background, the convex hull of the mesh stands in for it until then. The mesh itself is
+    /// used if the decomposition failed.
     pub fn get_compound_convex_shapes(&self, scale: Vector<f32>) -> SharedShape {
-        let shape = self.shape.clone().scaled(&scale);
-        SharedShape::convex_decomposition(shape.vertices(), shape.indices())
+        match self.decomposition() {
+            Decomposition::Ready(decomposition) => scaled_decomposition(&decomposition, scale),
+            Decomposition::Pending => {
+                self.pending_decomposition.set(true);
+                let shape = self.shape.clone().scaled(&scale);
+                SharedShape::convex_hull(shape.vertices())
+                    .unwrap_or_else(|| SharedShape::new(shape))
+            }
+            Decomposition::Failed => self.build_shared_shape(scale),
+        }
+    }
+
+    fn rebuild_trimesh(&mut self) {
+        let (vertices, indices) = generate_indices(&self.faces, self.backface_collision);
+        self.shape = if indices.is_empty() {
+            placeholder_trimesh()
+        } else {
+            TriMesh::new(vertices, indices)
+        };
+        self.mesh_hash = mesh_hash(self.shape.vertices(), self.shape.indices());
+        *self.decomposition.get_mut() = None;
     }
 }
 
@@ -74,14 +140,14 @@ impl RapierShape for RapierConcaveShape {
 
     fn data(&self) -> Variant {
         let faces = self
-            .shape
-            .vertices()
+            .faces
             .iter()
             .map(|v| Vector3::new(v.x, v.y, v.z))
             .collect::<Array<Vector3>>();
 
         Variant::from(dict! {
-            "faces" : faces, "backface_collision":self.backface_collision
+            "faces" : faces, "backface_collision":self.backface_collision,
+            "decomposition": self.decomposition_params.to_dictionary()
         })
     }
 
@@ -92,14 +158,25 @@ impl RapierShape for RapierConcaveShape {
                     Ok(value) => {
                         if value != self.backface_collision {
                             self.backface_collision = value;
-                            let (vertices, indices) =
-                                generate_indices(self.shape.vertices(), self.backface_collision);
-                            self.shape = TriMesh::new(vertices, indices);
-                            self.update_owners();
+                            self.rebuild_trimesh();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
                 }
+                if let Some(decomposition) = d.get("decomposition") {
+                    match decomposition.try_to::<Dictionary>() {
+                        Ok(decomposition) => {
+                            let params = ConvexDecompositionParams::from_dictionary(&decomposition);
+                            if params != self.decomposition_params {
+                                self.decomposition_params = params;
+                                *self.decomposition.get_mut() = None;
+                                self.shared_shapes.invalidate();
+                            }
+                        }
+                        Err(e) => godot_error!("{:?}", e),
+                    }
+                }
                 match d.get_or_nil("faces").try_to::<PackedVector3Array>() {
                     Ok(vertices) => {
                         let vertices: Vec<Point<f32>> = vertices
@@ -107,11 +184,10 @@ impl RapierShape for RapierConcaveShape {
                             .iter()
                             .map(|v| point![v.x, v.y, v.z])
                             .collect();
-                        if vertices != *self.shape.vertices() {
-                            let (vertices, indices) =
-                                generate_indices(&vertices, self.backface_collision);
-                            self.shape = TriMesh::new(vertices, indices);
-                            self.update_owners();
+                        if vertices != self.faces {
+                            self.faces = vertices;
+                            self.rebuild_trimesh();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -121,9 +197,37 @@ impl RapierShape for RapierConcaveShape {
         }
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
-        //SharedShape::new(self.shape.scaled(&scale))
-        self.get_compound_convex_shapes(scale)
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        SharedShape::new(self.shape.clone().scaled(&scale))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.faces.len() < 3
+    }
+
+    fn dynamic_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        if self.is_empty() {
+            return self.shared_shape(scale);
+        }
+        self.shared_shapes
+            .get_or_insert_with(scale, true, || self.get_compound_convex_shapes(scale))
+    }
+
+    fn poll_background_work(&self) {
+        if !self.pending_decomposition.get() {
+            return;
+        }
+        match self.decomposition() {
+            Decomposition::Pending => return,
+            Decomposition::Ready(_) => {}
+            Decomposition::Failed => godot_error!(
+                "The convex decomposition of {} failed, dynamic bodies collide with its mesh",
+                self.rid
+            ),
+        }
+        self.pending_decomposition.set(false);
+        self.shared_shapes.invalidate();
+        self.update_owners_if_invalidated();
     }
 
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
@@ -136,4 +240,7 @@ impl RapierShape for RapierConcaveShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
 }
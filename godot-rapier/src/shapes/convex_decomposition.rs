@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+    },
+    thread,
+};
+
+use godot::prelude::*;
+use rapier3d::{parry::transformation::vhacd::VHACDParameters, prelude::*};
+
+use super::geometry_cache::{
+    content_hash, geometry_cache_dir, load_convex_parts, store_convex_parts,
+};
+
+/// The VHACD parameters exposed in the concave shape data.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ConvexDecompositionParams {
+    pub resolution: u32,
+    pub concavity: f32,
+    pub max_hulls: u32,
+}
+
+impl Default for ConvexDecompositionParams {
+    fn default() -> Self {
+        let params = VHACDParameters::default();
+        Self {
+            resolution: params.resolution,
+            concavity: params.concavity,
+            max_hulls: params.max_convex_hulls,
+        }
+    }
+}
+
+impl ConvexDecompositionParams {
+    /// Reads the parameters of a `"decomposition"` Dictionary, missing entries keep their
+    /// default value.
+    pub fn from_dictionary(d: &Dictionary) -> Self {
+        let mut params = Self::default();
+        if let Some(resolution) = d.get("resolution") {
+            match resolution.try_to::<u32>() {
+                Ok(resolution) => params.resolution = resolution,
+                Err(e) => godot_error!("{:?}", e),
+            }
+        }
+        if let Some(concavity) = d.get("concavity") {
+            match concavity.try_to::<f32>() {
+                Ok(concavity) => params.concavity = concavity,
+                Err(e) => godot_error!("{:?}", e),
+            }
+        }
+        if let Some(max_hulls) = d.get("max_hulls") {
+            match max_hulls.try_to::<u32>() {
+                Ok(max_hulls) => params.max_hulls = max_hulls,
+                Err(e) => godot_error!("{:?}", e),
+            }
+        }
+        params
+    }
+
+    pub fn to_dictionary(self) -> Dictionary {
+        dict! {
+            "resolution": self.resolution,
+            "concavity": self.concavity,
+            "max_hulls": self.max_hulls,
+        }
+    }
+
+    fn vhacd_parameters(self) -> VHACDParameters {
+        VHACDParameters {
+            resolution: self.resolution,
+            concavity: self.concavity,
+            max_convex_hulls: self.max_hulls,
+            ..VHACDParameters::default()
+        }
+    }
+}
+
+/// Mesh hash and parameters identifying a decomposition.
+type DecompositionKey = (u64, u32, u32, u32);
+
+/// Progress of a decomposition requested by a concave shape.
+#[derive(Clone)]
+pub enum Decomposition {
+    /// Queued or being computed by a worker thread.
+    Pending,
+    Ready(SharedShape),
+    /// The worker panicked, the shape should keep using its mesh.
+    Failed,
+}
+
+/// A decomposition and the number of concave shapes using it.
+struct DecompositionEntry {
+    decomposition: Decomposition,
+    users: usize,
+}
+
+/// Worker threads computing decompositions, more meshes queue up rather than get a thread each.
+const MAX_DECOMPOSITION_WORKERS: usize = 4;
+
+/// Decompositions shared by every concave shape, evicted when the last shape using one lets go
+/// of it.
+fn decompositions() -> MutexGuard<'static, HashMap<DecompositionKey, DecompositionEntry>> {
+    static DECOMPOSITIONS: OnceLock<Mutex<HashMap<DecompositionKey, DecompositionEntry>>> =
+        OnceLock::new();
+    // Workers never panic while holding the lock, the map is consistent even if poisoned.
+    DECOMPOSITIONS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Set when a worker thread finished a decomposition that shapes haven't picked up yet.
+static DECOMPOSITION_COMPLETED: AtomicBool = AtomicBool::new(false);
+
+struct DecompositionJob {
+    key: DecompositionKey,
+    cache_key: String,
+    cache_dir: &'static Path,
+    vertices: Vec<Point<f32>>,
+    indices: Vec<[u32; 3]>,
+    params: ConvexDecompositionParams,
+}
+
+impl DecompositionJob {
+    fn run(self) {
+        let key = self.key;
+        if !decompositions().contains_key(&key) {
+            return;
+        }
+        let decomposition = panic::catch_unwind(AssertUnwindSafe(|| self.decompose()))
+            .map_or(Decomposition::Failed, Decomposition::Ready);
+        // The shapes may have let go of the decomposition while it was computed.
+        if let Some(entry) = decompositions().get_mut(&key) {
+            entry.decomposition = decomposition;
+        }
+        DECOMPOSITION_COMPLETED.store(true, Ordering::Release);
+    }
+
+    fn decompose(&self) -> SharedShape {
+        if let Some(parts) = load_convex_parts(
+            self.cache_dir,
+            &self.cache_key,
+            &self.vertices,
+            &self.indices,
+        ) {
+            return SharedShape::compound(
+                parts
+                    .into_iter()
+                    .map(|part| (Isometry::identity(), SharedShape::new(part)))
+                    .collect(),
+            );
+        }
+        let decomposition = SharedShape::convex_decomposition_with_params(
+            &self.vertices,
+            &self.indices,
+            &self.params.vhacd_parameters(),
+        );
+        if let Some(compound) = decomposition.as_compound() {
+            store_convex_parts(
+                self.cache_dir,
+                &self.cache_key,
+                &self.vertices,
+                &self.indices,
+                compound
+                    .shapes()
+                    .iter()
+                    .filter_map(|(_, part)| part.as_convex_polyhedron()),
+            );
+        }
+        decomposition
+    }
+}
+
+/// Queues `job` for the worker threads, starting them the first time.
+fn queue_job(job: DecompositionJob) {
+    static JOBS: OnceLock<Mutex<Sender<DecompositionJob>>> = OnceLock::new();
+    let jobs = JOBS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<DecompositionJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(MAX_DECOMPOSITION_WORKERS);
+        for _ in 0..workers {
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                let job = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                match job {
+                    Ok(job) => job.run(),
+                    Err(_) => break,
+                }
+            });
+        }
+        Mutex::new(sender)
+    });
+    let key = job.key;
+    if jobs
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .send(job)
+        .is_err()
+    {
+        if let Some(entry) = decompositions().get_mut(&key) {
+            entry.decomposition = Decomposition::Failed;
+        }
+    }
+}
+
+pub fn mesh_hash(vertices: &[Point<f32>], indices: &[[u32; 3]]) -> u64 {
+    content_hash(vertices, indices)
+}
+
+/// A concave shape's use of the decomposition of its mesh. The decomposition is evicted when
+/// its last user is dropped.
+pub struct DecompositionUser {
+    key: DecompositionKey,
+}
+
+impl DecompositionUser {
+    /// Uses the decomposition of the mesh, queuing loading it from the geometry cache, or
+    /// computing it, for a worker thread if no other shape uses it.
+    pub fn new(
+        mesh_hash: u64,
+        vertices: &[Point<f32>],
+        indices: &[[u32; 3]],
+        params: ConvexDecompositionParams,
+    ) -> Self {
+        let key = (
+            mesh_hash,
+            params.resolution,
+            params.concavity.to_bits(),
+            params.max_hulls,
+        );
+        let mut computed = decompositions();
+        if let Some(entry) = computed.get_mut(&key) {
+            entry.users += 1;
+            return Self { key };
+        }
+        computed.insert(
+            key,
+            DecompositionEntry {
+                decomposition: Decomposition::Pending,
+                users: 1,
+            },
+        );
+        drop(computed);
+        queue_job(DecompositionJob {
+            key,
+            cache_key: format!(
+                "decomposition-{:016x}-{}-{:08x}-{}",
+                key.0, key.1, key.2, key.3
+            ),
+            cache_dir: geometry_cache_dir(),
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+            params,
+        });
+        Self { key }
+    }
+
+    /// The unscaled decomposition of the mesh, `Decomposition::Pending` until a worker thread
+    /// finished it.
+    pub fn decomposition(&self) -> Decomposition {
+        decompositions()
+            .get(&self.key)
+            .map_or(Decomposition::Failed, |entry| entry.decomposition.clone())
+    }
+}
+
+impl Drop for DecompositionUser {
+    fn drop(&mut self) {
+        let mut computed = decompositions();
+        if let Some(entry) = computed.get_mut(&self.key) {
+            entry.users -= 1;
+            if entry.users == 0 {
+                computed.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Whether decompositions finished since the last call, shapes waiting on one should then poll.
+pub fn take_completed_decompositions() -> bool {
+    DECOMPOSITION_COMPLETED.swap(false, Ordering::AcqRel)
+}
+
+/// Scales the convex parts of an unscaled decomposition.
+pub fn scaled_decomposition(decomposition: &SharedShape, scale: Vector<f32>) -> SharedShape {
+    let Some(compound) = decomposition.as_compound() else {
+        return decomposition.clone();
+    };
+    let parts: Vec<(Isometry<f32>, SharedShape)> = compound
+        .shapes()
+        .iter()
+        .filter_map(|(isometry, part)| {
+            let convex = part.as_convex_polyhedron()?.clone().scaled(&scale)?;
+            let mut isometry = *isometry;
+            isometry.translation.vector.component_mul_assign(&scale);
+            Some((isometry, SharedShape::new(convex)))
+        })
+        .collect();
+    if parts.is_empty() {
+        godot_error!("Scaling one of the collision shape axis to 0");
+        return decomposition.clone();
+    }
+    SharedShape::compound(parts)
+}
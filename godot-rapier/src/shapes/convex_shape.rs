@@ -7,10 +7,16 @@ use rapier3d::prelude::*;
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{
+    geometry_cache::{content_hash, geometry_cache_dir, load_convex_parts, store_convex_parts},
+    rounded_convex_polyhedron, RapierShape, SharedShapeCache,
+};
 pub struct RapierConvexShape {
     shape: ConvexPolyhedron,
+    /// The points the hull was built from, which it may not all keep.
+    points: Vec<Point<f32>>,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
     margin: f32,
 }
@@ -23,7 +29,9 @@ impl RapierConvexShape {
                 point![0.0, 0.0, 1.0],
             ])
             .unwrap(),
+            points: vec![],
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
             margin: 0.0,
         }
@@ -52,10 +60,11 @@ impl RapierShape for RapierConvexShape {
                     .iter_shared()
                     .map(|v| point![v.x, v.y, v.z])
                     .collect();
-                if self.shape.points() != points {
-                    if let Some(convex) = ConvexPolyhedron::from_convex_hull(&points) {
+                if self.points != points {
+                    if let Some(convex) = convex_hull(&points) {
                         self.shape = convex;
-                        self.update_owners();
+                        self.points = points;
+                        self.shared_shapes.invalidate();
                     }
                 }
             }
@@ -63,7 +72,7 @@ impl RapierShape for RapierConvexShape {
         }
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
         let shape = self.shape.clone().scaled(&scale).map_or_else(
             || {
                 godot_error!("Scaling one of the collision shape axis to 0");
@@ -71,14 +80,7 @@ impl RapierShape for RapierConvexShape {
             },
             |scaled_shape| scaled_shape,
         );
-        if self.margin.is_zero_approx() {
-            SharedShape::new(shape)
-        } else {
-            SharedShape::new(RoundConvexPolyhedron {
-                inner_shape: self.shape.clone(),
-                border_radius: self.margin,
-            })
-        }
+        rounded_convex_polyhedron(shape, self.margin)
     }
 
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
@@ -91,10 +93,13 @@ impl RapierShape for RapierConvexShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
     fn set_margin(&mut self, margin: f32) {
-        if margin.approx_eq(&self.margin) {
+        if !margin.approx_eq(&self.margin) {
             self.margin = margin;
-            self.update_owners();
+            self.shared_shapes.invalidate();
         }
     }
 
@@ -102,3 +107,17 @@ impl RapierShape for RapierConvexShape {
         self.margin
     }
 }
+
+/// Convex hull of `points`, read from the geometry cache when it was computed before.
+fn convex_hull(points: &[Point<f32>]) -> Option<ConvexPolyhedron> {
+    let cache_dir = geometry_cache_dir();
+    let cache_key = format!("hull-{:016x}", content_hash(points, &[]));
+    if let Some(convex) =
+        load_convex_parts(cache_dir, &cache_key, points, &[]).and_then(|mut parts| parts.pop())
+    {
+        return Some(convex);
+    }
+    let convex = ConvexPolyhedron::from_convex_hull(points)?;
+    store_convex_parts(cache_dir, &cache_key, points, &[], [&convex]);
+    Some(convex)
+}
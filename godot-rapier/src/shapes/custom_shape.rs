@@ -0,0 +1,226 @@
+#![allow(clippy::module_name_repetitions)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use godot::prelude::*;
+use rapier3d::{parry::either::Either, prelude::*};
+
+use crate::{collision_object::RapierCollisionObject, conversions::IntoExt, error::RapierError};
+
+use super::{round_shape_scale, RapierShape, SharedShapeCache, DEFAULT_SCALED_SUBDIVISIONS};
+
+/// Rapier primitives Godot has no shape for, described by the Dictionary given to
+/// `shape_set_data`. The `"type"` key selects the primitive:
+/// - `"cone"`: `"radius"` and `"height"`, along Y.
+/// - `"round_box"`: `"half_extents"` (Vector3) of the inner box and `"border_radius"`. The
+///   border radius is scaled by the smallest axis of the object scale.
+/// - `"triangle"`: `"points"`, three Vector3.
+/// - `"polyline"`: `"points"`, at least two Vector3.
+/// - `"segment"`: `"a"` and `"b"` (Vector3).
+/// - `"compound"`: `"shapes"`, an Array of primitive descriptions, each with an optional
+///   `"transform"` (Transform3D). Polylines and compounds cannot be nested in a compound.
+enum CustomShapeKind {
+    Cone(Cone),
+    RoundBox(RoundCuboid),
+    Triangle(Triangle),
+    Polyline(Polyline),
+    Segment(Segment),
+    Compound(Vec<(Isometry<f32>, CustomShapeKind)>),
+}
+
+fn get_f32(d: &Dictionary, key: &str) -> Result<f32, RapierError> {
+    d.get_or_nil(key)
+        .try_to::<f32>()
+        .map_err(|e| RapierError::InvalidCustomShapeData(format!("{key}: {e:?}")))
+}
+
+fn get_point(d: &Dictionary, key: &str) -> Result<Point<f32>, RapierError> {
+    d.get_or_nil(key)
+        .try_to::<Vector3>()
+        .map(IntoExt::into_ext)
+        .map_err(|e| RapierError::InvalidCustomShapeData(format!("{key}: {e:?}")))
+}
+
+fn get_points(d: &Dictionary, key: &str) -> Result<Vec<Point<f32>>, RapierError> {
+    d.get_or_nil(key)
+        .try_to::<PackedVector3Array>()
+        .map(|points| points.to_vec().into_iter().map(IntoExt::into_ext).collect())
+        .map_err(|e| RapierError::InvalidCustomShapeData(format!("{key}: {e:?}")))
+}
+
+impl CustomShapeKind {
+    fn from_dictionary(d: &Dictionary) -> Result<Self, RapierError> {
+        let kind = d
+            .get_or_nil("type")
+            .try_to::<String>()
+            .map_err(|e| RapierError::InvalidCustomShapeData(format!("type: {e:?}")))?;
+        match kind.as_str() {
+            "cone" => Ok(Self::Cone(Cone::new(
+                get_f32(d, "height")? * 0.5,
+                get_f32(d, "radius")?,
+            ))),
+            "round_box" => {
+                let half_extents = get_point(d, "half_extents")?;
+                Ok(Self::RoundBox(RoundCuboid {
+                    inner_shape: Cuboid::new(half_extents.coords),
+                    border_radius: get_f32(d, "border_radius")?,
+                }))
+            }
+            "triangle" => match get_points(d, "points")?[..] {
+                [a, b, c] => Ok(Self::Triangle(Triangle::new(a, b, c))),
+                _ => Err(RapierError::InvalidCustomShapeData(
+                    "a triangle needs exactly 3 points".to_string(),
+                )),
+            },
+            "polyline" => {
+                let points = get_points(d, "points")?;
+                if points.len() < 2 {
+                    return Err(RapierError::InvalidCustomShapeData(
+                        "a polyline needs at least 2 points".to_string(),
+                    ));
+                }
+                Ok(Self::Polyline(Polyline::new(points, None)))
+            }
+            "segment" => Ok(Self::Segment(Segment::new(
+                get_point(d, "a")?,
+                get_point(d, "b")?,
+            ))),
+            "compound" => {
+                let shapes = d
+                    .get_or_nil("shapes")
+                    .try_to::<Array<Dictionary>>()
+                    .map_err(|e| RapierError::InvalidCustomShapeData(format!("shapes: {e:?}")))?;
+                let mut children = Vec::with_capacity(shapes.len());
+                for child in shapes.iter_shared() {
+                    let isometry = child
+                        .get_or_nil("transform")
+                        .try_to::<Transform3D>()
+                        .map_or_else(
+                            |_| Isometry::identity(),
+                            |transform| {
+                                let (isometry, _) = transform.into_ext();
+                                isometry
+                            },
+                        );
+                    match Self::from_dictionary(&child)? {
+                        Self::Polyline(_) | Self::Compound(_) => {
+                            return Err(RapierError::InvalidCustomShapeData(
+                                "compounds only accept cones, round boxes, triangles and segments"
+                                    .to_string(),
+                            ))
+                        }
+                        kind => children.push((isometry, kind)),
+                    }
+                }
+                if children.is_empty() {
+                    return Err(RapierError::InvalidCustomShapeData(
+                        "a compound needs at least one shape".to_string(),
+                    ));
+                }
+                Ok(Self::Compound(children))
+            }
+            _ => Err(RapierError::InvalidCustomShapeData(format!(
+                "unknown type {kind}"
+            ))),
+        }
+    }
+
+    /// Builds the shape at `scale`. Compound children are scaled along the compound axes, which
+    /// is only exact for children that are not rotated.
+    fn build(&self, scale: Vector<f32>) -> SharedShape {
+        match self {
+            Self::Cone(cone) => cone
+                .scaled(&round_shape_scale(scale), DEFAULT_SCALED_SUBDIVISIONS)
+                .map_or_else(
+                    || {
+                        godot_error!("Scaling one of the collision shape axis to 0");
+                        SharedShape::new(*cone)
+                    },
+                    |scaled_shape| match scaled_shape {
+                        Either::Left(cone) => SharedShape::new(cone),
+                        Either::Right(convex_poly) => SharedShape::new(convex_poly),
+                    },
+                ),
+            // The border stays round, so it follows the smallest scale axis.
+            Self::RoundBox(round_box) => SharedShape::new(RoundCuboid {
+                inner_shape: round_box.inner_shape.scaled(&scale),
+                border_radius: round_box.border_radius * scale.abs().min(),
+            }),
+            Self::Triangle(triangle) => SharedShape::new(triangle.scaled(&scale)),
+            Self::Polyline(polyline) => SharedShape::new(polyline.clone().scaled(&scale)),
+            Self::Segment(segment) => SharedShape::new(segment.scaled(&scale)),
+            Self::Compound(children) => SharedShape::compound(
+                children
+                    .iter()
+                    .map(|(isometry, child)| {
+                        let mut isometry = *isometry;
+                        isometry.translation.vector.component_mul_assign(&scale);
+                        (isometry, child.build(scale))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+pub struct RapierCustomShape {
+    shape: CustomShapeKind,
+    data: Dictionary,
+    owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
+    rid: Rid,
+}
+
+impl RapierCustomShape {
+    pub fn new(rid: Rid) -> Self {
+        Self {
+            shape: CustomShapeKind::Cone(Cone::new(0.5, 0.5)),
+            data: dict! {"type": "cone", "radius": 0.5, "height": 1.0},
+            owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
+            rid,
+        }
+    }
+}
+
+impl RapierShape for RapierCustomShape {
+    fn rid(&self) -> Rid {
+        self.rid
+    }
+
+    fn data(&self) -> Variant {
+        Variant::from(self.data.clone())
+    }
+
+    fn set_data(&mut self, data: Variant) {
+        match data.try_to::<Dictionary>() {
+            Ok(d) => match CustomShapeKind::from_dictionary(&d) {
+                Ok(shape) => {
+                    self.shape = shape;
+                    self.data = d;
+                    self.shared_shapes.invalidate();
+                }
+                Err(e) => godot_error!("{}", e),
+            },
+            Err(e) => godot_error!("{:?}", e),
+        }
+    }
+
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shape.build(scale)
+    }
+
+    fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
+        godot::engine::physics_server_3d::ShapeType::SHAPE_CUSTOM
+    }
+
+    fn owners(&self) -> &Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
+        &self.owners
+    }
+    fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
+        &mut self.owners
+    }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
+}
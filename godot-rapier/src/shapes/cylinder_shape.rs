@@ -5,12 +5,18 @@ use rapier3d::{parry::either::Either, prelude::*};
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{
+    data_subdivisions, round_shape_scale, rounded_convex_polyhedron, rounded_cylinder, RapierShape,
+    SharedShapeCache, DEFAULT_SCALED_SUBDIVISIONS,
+};
 
 pub struct RapierCylinderShape {
     shape: Cylinder,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
+    /// Subdivisions of the convex approximation used under non-uniform scale.
+    subdivisions: u32,
     margin: f32,
 }
 
@@ -19,7 +25,9 @@ impl RapierCylinderShape {
         Self {
             shape: Cylinder::new(0.5, 0.2),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
+            subdivisions: DEFAULT_SCALED_SUBDIVISIONS,
             margin: 0.0,
         }
     }
@@ -27,17 +35,27 @@ impl RapierCylinderShape {
 
 impl RapierShape for RapierCylinderShape {
     fn data(&self) -> Variant {
-        Variant::from(dict! {"radius": self.shape.radius,"height":self.shape.half_height*2.0})
+        let mut data = dict! {"radius": self.shape.radius,"height":self.shape.half_height*2.0};
+        if self.subdivisions != DEFAULT_SCALED_SUBDIVISIONS {
+            data.set("subdivisions", self.subdivisions);
+        }
+        Variant::from(data)
     }
 
     fn set_data(&mut self, data: Variant) {
         match data.try_to::<Dictionary>() {
             Ok(d) => {
+                if let Some(subdivisions) = data_subdivisions(&d) {
+                    if subdivisions != self.subdivisions {
+                        self.subdivisions = subdivisions;
+                        self.shared_shapes.invalidate();
+                    }
+                }
                 match d.get_or_nil("radius").try_to::<f32>() {
                     Ok(radius) => {
                         if !radius.approx_eq(&self.shape.radius) {
                             self.shape.radius = radius;
-                            self.update_owners();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -46,7 +64,7 @@ impl RapierShape for RapierCylinderShape {
                     Ok(height) => {
                         if !height.approx_eq(&(self.shape.half_height * 2.0)) {
                             self.shape.half_height = height * 0.5;
-                            self.update_owners();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -55,42 +73,21 @@ impl RapierShape for RapierCylinderShape {
             Err(e) => godot_error!("{:?}", e),
         };
     }
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
-        self.shape.scaled(&scale, 3).map_or_else(
-            || {
-                godot_error!("Scaling one of the collision shape axis to 0");
-                if self.margin.is_zero_approx() {
-                    SharedShape::new(self.shape)
-                } else {
-                    SharedShape::new(RoundCylinder {
-                        inner_shape: self.shape,
-                        border_radius: self.margin,
-                    })
-                }
-            },
-            |scaled_shape| match scaled_shape {
-                Either::Left(capsule) => {
-                    if self.margin().is_zero_approx() {
-                        SharedShape::new(capsule)
-                    } else {
-                        SharedShape::new(RoundCylinder {
-                            inner_shape: capsule,
-                            border_radius: self.margin,
-                        })
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shape
+            .scaled(&round_shape_scale(scale), self.subdivisions)
+            .map_or_else(
+                || {
+                    godot_error!("Scaling one of the collision shape axis to 0");
+                    rounded_cylinder(self.shape, self.margin)
+                },
+                |scaled_shape| match scaled_shape {
+                    Either::Left(cylinder) => rounded_cylinder(cylinder, self.margin),
+                    Either::Right(convex_poly) => {
+                        rounded_convex_polyhedron(convex_poly, self.margin)
                     }
-                }
-                Either::Right(convex_poly) => {
-                    if self.margin().is_zero_approx() {
-                        SharedShape::new(convex_poly)
-                    } else {
-                        SharedShape::new(RoundConvexPolyhedron {
-                            inner_shape: convex_poly,
-                            border_radius: self.margin,
-                        })
-                    }
-                }
-            },
-        )
+                },
+            )
     }
 
     fn rid(&self) -> Rid {
@@ -102,13 +99,16 @@ impl RapierShape for RapierCylinderShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_CYLINDER
     }
     fn set_margin(&mut self, margin: f32) {
-        if margin.approx_eq(&self.margin) {
+        if !margin.approx_eq(&self.margin) {
             self.margin = margin;
-            self.update_owners();
+            self.shared_shapes.invalidate();
         }
     }
 
@@ -0,0 +1,234 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use godot::{engine::ProjectSettings, prelude::*};
+use rapier3d::prelude::*;
+
+/// Convex hulls and decompositions are written under this directory, one file per input mesh
+/// and parameters. Deleting it only means they get computed again.
+const GEOMETRY_CACHE_DIR: &str = "user://rapier_geometry_cache";
+/// Bumped when the file layout changes, files of other versions are ignored.
+const GEOMETRY_CACHE_VERSION: u32 = 2;
+const GEOMETRY_CACHE_MAGIC: &[u8; 4] = b"GRGC";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Hash of the vertices and indices of a mesh. Unlike the std hashers it is stable across
+/// builds, so it can name cache files.
+pub fn content_hash(vertices: &[Point<f32>], indices: &[[u32; 3]]) -> u64 {
+    let words = vertices
+        .iter()
+        .flat_map(|vertex| [vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()])
+        .chain(indices.iter().flatten().copied());
+    let mut hash = FNV_OFFSET_BASIS;
+    for word in words {
+        for byte in word.to_le_bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// Directory of the cache on disk. Must first be called from the main thread, as it resolves
+/// the `user://` path through Godot.
+pub fn geometry_cache_dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let path = ProjectSettings::singleton().globalize_path(GEOMETRY_CACHE_DIR.into());
+        PathBuf::from(path.to_string())
+    })
+}
+
+/// Reads the convex parts computed from `vertices` and `indices` stored under `key`, `None` if
+/// they are missing, unreadable or were computed from another mesh with the same key.
+pub fn load_convex_parts(
+    dir: &Path,
+    key: &str,
+    vertices: &[Point<f32>],
+    indices: &[[u32; 3]],
+) -> Option<Vec<ConvexPolyhedron>> {
+    let mut bytes = vec![];
+    fs::File::open(dir.join(key))
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .ok()?;
+    let mut reader = ByteReader { bytes: &bytes };
+    if reader.take(4)? != GEOMETRY_CACHE_MAGIC || reader.u32()? != GEOMETRY_CACHE_VERSION {
+        return None;
+    }
+    // The key is only a hash of the input, the input itself is stored to rule out collisions.
+    let same_vertices = reader
+        .points()?
+        .iter()
+        .map(bits)
+        .eq(vertices.iter().map(bits));
+    if !same_vertices || reader.indices()? != indices {
+        return None;
+    }
+    let part_count = reader.u32()?;
+    let mut parts = vec![];
+    for _ in 0..part_count {
+        let points = reader.points()?;
+        let indices = reader.indices()?;
+        if indices
+            .iter()
+            .flatten()
+            .any(|idx| *idx as usize >= points.len())
+        {
+            return None;
+        }
+        parts.push(ConvexPolyhedron::from_convex_mesh(points, &indices)?);
+    }
+    Some(parts)
+}
+
+/// Writes the convex parts computed from `vertices` and `indices` under `key`. Failing to write
+/// only loses the cache entry.
+#[allow(clippy::cast_possible_truncation)]
+pub fn store_convex_parts<'a>(
+    dir: &Path,
+    key: &str,
+    vertices: &[Point<f32>],
+    indices: &[[u32; 3]],
+    parts: impl IntoIterator<Item = &'a ConvexPolyhedron>,
+) {
+    let mut bytes = GEOMETRY_CACHE_MAGIC.to_vec();
+    bytes.extend(GEOMETRY_CACHE_VERSION.to_le_bytes());
+    write_points(&mut bytes, vertices);
+    write_indices(&mut bytes, indices);
+    let parts: Vec<_> = parts
+        .into_iter()
+        .map(ConvexPolyhedron::to_trimesh)
+        .collect();
+    bytes.extend((parts.len() as u32).to_le_bytes());
+    for (points, indices) in &parts {
+        write_points(&mut bytes, points);
+        write_indices(&mut bytes, indices);
+    }
+    if let Err(e) = write_atomically(dir, key, &bytes) {
+        godot_warn!("Couldn't write the Rapier geometry cache: {e}");
+    }
+}
+
+/// Compares points bit for bit, like `content_hash` does.
+fn bits(point: &Point<f32>) -> [u32; 3] {
+    [point.x.to_bits(), point.y.to_bits(), point.z.to_bits()]
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_points(bytes: &mut Vec<u8>, points: &[Point<f32>]) {
+    bytes.extend((points.len() as u32).to_le_bytes());
+    for point in points {
+        for coord in point.iter() {
+            bytes.extend(coord.to_le_bytes());
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_indices(bytes: &mut Vec<u8>, indices: &[[u32; 3]]) {
+    bytes.extend((indices.len() as u32).to_le_bytes());
+    for idx in indices.iter().flatten() {
+        bytes.extend(idx.to_le_bytes());
+    }
+}
+
+/// Writes through a temporary file so that readers never see a partial entry.
+fn write_atomically(dir: &Path, key: &str, bytes: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let tmp_path = dir.join(format!("{key}.{}.tmp", std::process::id()));
+    fs::File::create(&tmp_path)?.write_all(bytes)?;
+    fs::rename(tmp_path, dir.join(key))
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn points(&mut self) -> Option<Vec<Point<f32>>> {
+        let count = self.u32()?;
+        let mut points = vec![];
+        for _ in 0..count {
+            points.push(point![self.f32()?, self.f32()?, self.f32()?]);
+        }
+        Some(points)
+    }
+
+    fn indices(&mut self) -> Option<Vec<[u32; 3]>> {
+        let count = self.u32()?;
+        let mut indices = vec![];
+        for _ in 0..count {
+            indices.push([self.u32()?, self.u32()?, self.u32()?]);
+        }
+        Some(indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> ConvexPolyhedron {
+        ConvexPolyhedron::from_convex_hull(&[
+            point![-1.0, -1.0, -1.0],
+            point![1.0, -1.0, -1.0],
+            point![-1.0, 1.0, -1.0],
+            point![1.0, 1.0, -1.0],
+            point![-1.0, -1.0, 1.0],
+            point![1.0, -1.0, 1.0],
+            point![-1.0, 1.0, 1.0],
+            point![1.0, 1.0, 1.0],
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn entries_of_another_mesh_are_not_loaded() {
+        let dir = std::env::temp_dir().join(format!("rapier-geometry-{}", std::process::id()));
+        let vertices = [
+            point![0.0, 0.0, 0.0],
+            point![1.0, 0.0, 0.0],
+            point![0.0, 1.0, 0.0],
+        ];
+        let indices = [[0, 1, 2]];
+        store_convex_parts(&dir, "mesh", &vertices, &indices, [&cube()]);
+
+        let parts = load_convex_parts(&dir, "mesh", &vertices, &indices).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].points().len(), cube().points().len());
+
+        let moved = [
+            point![0.0, 0.0, 0.0],
+            point![2.0, 0.0, 0.0],
+            point![0.0, 1.0, 0.0],
+        ];
+        assert!(load_convex_parts(&dir, "mesh", &moved, &indices).is_none());
+        assert!(load_convex_parts(&dir, "mesh", &vertices, &[[0, 2, 1]]).is_none());
+        assert!(load_convex_parts(&dir, "mesh", &vertices[..2], &[]).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
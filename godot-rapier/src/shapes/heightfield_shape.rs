@@ -3,15 +3,25 @@
 use std::{cell::RefCell, rc::Rc};
 
 use godot::prelude::*;
-use rapier3d::{na::dmatrix, prelude::*};
+use rapier3d::{na::dmatrix, parry::shape::HeightFieldCellStatus, prelude::*};
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{RapierShape, SharedShapeCache};
+
+/// Heights at or above this magnitude, like NaN, mark holes in the heightmap.
+const HOLE_HEIGHT: f32 = 1.0e30;
+
+fn is_hole(height: f32) -> bool {
+    height.is_nan() || height.abs() >= HOLE_HEIGHT
+}
 
 pub struct RapierHeightmapShape {
     shape: HeightField,
+    /// Heights as set by Godot, holes included.
+    heights: PackedFloat32Array,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
 }
 
@@ -21,10 +31,9 @@ impl RapierShape for RapierHeightmapShape {
     }
 
     fn data(&self) -> Variant {
-        let heights: PackedFloat32Array = self.shape.heights().iter().copied().collect();
         let width = self.shape.ncols() as i32;
         let depth = self.shape.nrows() as i32;
-        Variant::from(dict! {"width":width,"depth":depth,"heights":heights})
+        Variant::from(dict! {"width":width,"depth":depth,"heights":self.heights.clone()})
     }
 
     fn set_data(&mut self, data: Variant) {
@@ -55,16 +64,19 @@ impl RapierShape for RapierHeightmapShape {
                     godot_error!("width * depth must equal heights(PackedFloat32Array) size");
                     return;
                 }
-                let heights = DMatrix::from_fn(depth, width, |i, j| heights.get(i * width + j));
-
-                self.shape = HeightField::new(heights, vector![1.0, 1.0, 1.0]);
-                self.update_owners();
+                if width < 2 || depth < 2 {
+                    godot_error!("HeightMapShape width and depth must be at least 2");
+                    return;
+                }
+                self.shape = Self::build_heightfield(width, depth, &heights);
+                self.heights = heights;
+                self.shared_shapes.invalidate();
             }
             Err(e) => godot_error!("{:?}", e),
         }
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
         SharedShape::new(self.shape.clone().scaled(&scale))
     }
 
@@ -78,14 +90,48 @@ impl RapierShape for RapierHeightmapShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
 }
 
 impl RapierHeightmapShape {
     pub fn new(rid: Rid) -> Self {
         Self {
             shape: HeightField::new(dmatrix![0.0,0.0;0.0,0.0], vector![1.0, 1.0, 1.0]),
+            heights: [0.0; 4].into_iter().collect(),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
         }
     }
+
+    /// Builds a heightfield laid out like Godot heightmaps: one unit per cell, rows along Z,
+    /// columns along X, centred on the origin horizontally with heights kept as is. Cells
+    /// touching a hole are removed.
+    fn build_heightfield(width: usize, depth: usize, heights: &PackedFloat32Array) -> HeightField {
+        let height_at = |i: usize, j: usize| heights.get(i * width + j);
+        let matrix = DMatrix::from_fn(depth, width, |i, j| {
+            let height = height_at(i, j);
+            if is_hole(height) {
+                0.0
+            } else {
+                height
+            }
+        });
+        let mut heightfield =
+            HeightField::new(matrix, vector![(width - 1) as f32, 1.0, (depth - 1) as f32]);
+        for i in 0..depth - 1 {
+            for j in 0..width - 1 {
+                if is_hole(height_at(i, j))
+                    || is_hole(height_at(i + 1, j))
+                    || is_hole(height_at(i, j + 1))
+                    || is_hole(height_at(i + 1, j + 1))
+                {
+                    heightfield.set_cell_status(i, j, HeightFieldCellStatus::CELL_REMOVED);
+                }
+            }
+        }
+        heightfield
+    }
 }
@@ -1,17 +1,25 @@
 #![allow(clippy::module_name_repetitions)]
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    sync::Arc,
+};
 
-use godot::prelude::*;
+use godot::prelude::{math::ApproxEq, *};
 use rapier3d::prelude::*;
 
-use crate::collision_object::RapierCollisionObject;
+use crate::{collision_object::RapierCollisionObject, contact_modifiers::SeparationRay};
 
 pub mod box_shape;
 pub mod capsule_shape;
 pub mod concave_shape;
+pub mod convex_decomposition;
 pub mod convex_shape;
+pub mod custom_shape;
 pub mod cylinder_shape;
+pub mod geometry_cache;
 pub mod heightfield_shape;
 pub mod separation_ray_shape;
 pub mod sphere_shape;
@@ -21,6 +29,7 @@ pub use self::box_shape::RapierBoxShape;
 pub use self::capsule_shape::RapierCapsuleShape;
 pub use self::concave_shape::RapierConcaveShape;
 pub use self::convex_shape::RapierConvexShape;
+pub use self::custom_shape::RapierCustomShape;
 pub use self::cylinder_shape::RapierCylinderShape;
 pub use self::heightfield_shape::RapierHeightmapShape;
 pub use self::separation_ray_shape::RapierSeparationRayShape;
@@ -28,20 +37,192 @@ pub use self::sphere_shape::RapierSphereShape;
 pub use self::world_boundary_shape::RapierWorldBoundaryShape;
 
 const DEFAULT_SOLVER_BIAS: f32 = 0.0;
+/// Subdivisions of the convex approximation of round shapes under non-uniform scale.
+pub const DEFAULT_SCALED_SUBDIVISIONS: u32 = 16;
+
+/// Scale of a round shape: mirroring doesn't change it, and components equal up to the
+/// rounding errors of the transform decomposition are snapped together so the shape stays an
+/// exact primitive.
+pub fn round_shape_scale(scale: Vector<f32>) -> Vector<f32> {
+    let mut scale = scale.abs();
+    if scale.y.approx_eq(&scale.x) {
+        scale.y = scale.x;
+    }
+    if scale.z.approx_eq(&scale.x) {
+        scale.z = scale.x;
+    } else if scale.z.approx_eq(&scale.y) {
+        scale.z = scale.y;
+    }
+    scale
+}
+
+/// Rounds a box by `margin` without growing it: the inner box is shrunk by the margin so the
+/// rounded box keeps the authored size. The margin is clamped to the smallest half extent.
+pub fn rounded_cuboid(shape: Cuboid, margin: f32) -> SharedShape {
+    if margin.is_zero_approx() {
+        return SharedShape::new(shape);
+    }
+    let border_radius = margin.min(shape.half_extents.abs().min()).max(0.0);
+    SharedShape::new(RoundCuboid {
+        inner_shape: Cuboid::new(shape.half_extents.abs().add_scalar(-border_radius)),
+        border_radius,
+    })
+}
+
+/// Rounds a cylinder by `margin` without growing it, like `rounded_cuboid`.
+pub fn rounded_cylinder(shape: Cylinder, margin: f32) -> SharedShape {
+    if margin.is_zero_approx() {
+        return SharedShape::new(shape);
+    }
+    let border_radius = margin.min(shape.half_height).min(shape.radius).max(0.0);
+    SharedShape::new(RoundCylinder {
+        inner_shape: Cylinder::new(
+            shape.half_height - border_radius,
+            shape.radius - border_radius,
+        ),
+        border_radius,
+    })
+}
+
+/// Rounds a convex polyhedron by `margin` without growing its bounds: the inner polyhedron is
+/// shrunk towards the center of its AABB by the margin along each axis. The margin is clamped to
+/// the smallest half extent, a polyhedron too thin for it is left sharp.
+pub fn rounded_convex_polyhedron(shape: ConvexPolyhedron, margin: f32) -> SharedShape {
+    if margin.is_zero_approx() {
+        return SharedShape::new(shape);
+    }
+    let aabb = shape.local_aabb();
+    let center = aabb.center();
+    let half_extents = aabb.half_extents();
+    let border_radius = margin.min(half_extents.min());
+    if border_radius <= 0.0 {
+        return SharedShape::new(shape);
+    }
+    let shrink = half_extents.map(|half_extent| (half_extent - border_radius) / half_extent);
+    let points: Vec<Point<f32>> = shape
+        .points()
+        .iter()
+        .map(|point| center + (point - center).component_mul(&shrink))
+        .collect();
+    match ConvexPolyhedron::from_convex_hull(&points) {
+        Some(inner_shape) => SharedShape::new(RoundConvexPolyhedron {
+            inner_shape,
+            border_radius,
+        }),
+        None => SharedShape::new(shape),
+    }
+}
+
+/// Reads the optional `"subdivisions"` entry of a round shape data Dictionary.
+pub fn data_subdivisions(d: &Dictionary) -> Option<u32> {
+    let subdivisions = d.get("subdivisions")?;
+    match subdivisions.try_to::<u32>() {
+        Ok(subdivisions) if subdivisions >= 3 => Some(subdivisions),
+        Ok(_) => {
+            godot_error!("Round shapes need at least 3 subdivisions");
+            None
+        }
+        Err(e) => {
+            godot_error!("{:?}", e);
+            None
+        }
+    }
+}
+
+/// Scales a shape keeps built shapes for. Past it, shapes are built for each owner, as when
+/// many owners use a shape at scales that keep changing.
+const MAX_CACHED_SHAPES: usize = 16;
+
+/// Shapes built by a `RapierShape` for each scale it is used at, shared by all its owners.
+/// Entries only live while a collider uses them, so that scaling an owner over time doesn't
+/// grow the cache, and there are at most `MAX_CACHED_SHAPES` of them.
+#[derive(Default)]
+pub struct SharedShapeCache {
+    shapes: RefCell<HashMap<([u32; 3], bool), SharedShape>>,
+    invalidated: Cell<bool>,
+}
+
+impl SharedShapeCache {
+    pub fn get_or_insert_with(
+        &self,
+        scale: Vector<f32>,
+        dynamic: bool,
+        build: impl FnOnce() -> SharedShape,
+    ) -> SharedShape {
+        let key = (
+            [scale.x.to_bits(), scale.y.to_bits(), scale.z.to_bits()],
+            dynamic,
+        );
+        if let Some(shape) = self.shapes.borrow().get(&key) {
+            return shape.clone();
+        }
+        let shape = build();
+        let mut shapes = self.shapes.borrow_mut();
+        shapes.retain(|_, cached| Arc::strong_count(&cached.0) > 1);
+        if shapes.len() < MAX_CACHED_SHAPES {
+            shapes.insert(key, shape.clone());
+        }
+        shape
+    }
+
+    /// Drops the cached shapes after the shape data changed, owners must then be updated.
+    pub fn invalidate(&self) {
+        self.shapes.borrow_mut().clear();
+        self.invalidated.set(true);
+    }
+
+    pub fn take_invalidated(&self) -> bool {
+        self.invalidated.replace(false)
+    }
+}
+
 pub trait RapierShape {
     fn rid(&self) -> Rid;
     fn data(&self) -> Variant;
     fn set_data(&mut self, data: Variant);
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape;
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape;
+    fn shared_shape_cache(&self) -> &SharedShapeCache;
+    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shared_shape_cache()
+            .get_or_insert_with(scale, false, || self.build_shared_shape(scale))
+    }
+    /// Whether the shape has no geometry, the colliders of empty shapes are disabled.
+    fn is_empty(&self) -> bool {
+        false
+    }
+    /// Shape to use when the owner is a dynamic body, for shapes that cannot be simulated as is.
+    fn dynamic_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shared_shape(scale)
+    }
+    /// Offset of the shape geometry from the shape transform, for shapes whose data includes a
+    /// position.
+    fn local_isometry(&self, scale: Vector<f32>) -> Isometry<f32> {
+        Isometry::identity()
+    }
+    /// How the shape collides if it is a separation ray, which only pushes along its ray.
+    fn separation_ray(&self) -> Option<SeparationRay> {
+        None
+    }
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType;
     fn owners(&self) -> &Vec<Rc<RefCell<dyn RapierCollisionObject>>>;
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>>;
 
+    /// Called by the server before each step, lets shapes update their owners once work done on
+    /// worker threads finished.
+    fn poll_background_work(&self) {}
+
     fn update_owners(&self) {
         for owner in self.owners() {
             owner.borrow_mut().update_shapes();
         }
     }
+    /// Rebuilds the owners colliders if the shape changed since the last call. Must be called
+    /// once the shape is no longer mutably borrowed, as owners borrow it to rebuild.
+    fn update_owners_if_invalidated(&self) {
+        if self.shared_shape_cache().take_invalidated() {
+            self.update_owners();
+        }
+    }
     fn add_owner(&mut self, owner: Rc<RefCell<dyn RapierCollisionObject>>) {
         self.owners_mut().push(owner);
     }
@@ -73,11 +254,50 @@ pub struct RapierShapeInstance {
     pub isometry: Isometry<f32>,
     pub disabled: bool,
     pub scale: Vector<f32>,
+    pub one_way_direction: Option<Vector<f32>>,
 }
 
 impl RapierShapeInstance {
-    pub fn shared_shape(&self) -> SharedShape {
-        self.shape.borrow().shared_shape(self.scale)
+    /// Scale of the shape geometry once its owner is scaled by `object_scale`. The geometry is
+    /// scaled along its own axes, which only matches the owner scale for uniform scales or
+    /// shapes that are not rotated.
+    pub fn combined_scale(&self, object_scale: Vector<f32>) -> Vector<f32> {
+        self.scale.component_mul(&object_scale)
+    }
+
+    pub fn shared_shape(&self, object_scale: Vector<f32>) -> SharedShape {
+        self.shape
+            .borrow()
+            .shared_shape(self.combined_scale(object_scale))
+    }
+
+    pub fn dynamic_shared_shape(&self, object_scale: Vector<f32>) -> SharedShape {
+        self.shape
+            .borrow()
+            .dynamic_shared_shape(self.combined_scale(object_scale))
+    }
+
+    pub fn separation_ray(&self) -> Option<SeparationRay> {
+        self.shape.borrow().separation_ray()
+    }
+
+    /// Whether the shape collides, it doesn't when disabled or without geometry.
+    pub fn is_enabled(&self) -> bool {
+        !self.disabled && !self.shape.borrow().is_empty()
+    }
+
+    /// Isometry of the shape geometry relative to its owner scaled by `object_scale`.
+    pub fn geometry_isometry(&self, object_scale: Vector<f32>) -> Isometry<f32> {
+        let mut isometry = self.isometry;
+        isometry
+            .translation
+            .vector
+            .component_mul_assign(&object_scale);
+        isometry
+            * self
+                .shape
+                .borrow()
+                .local_isometry(self.combined_scale(object_scale))
     }
 }
 
@@ -93,6 +313,80 @@ impl RapierShapeInstance {
             isometry,
             disabled,
             scale,
+            one_way_direction: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_aabb_eq(shape: &SharedShape, mins: Point<f32>, maxs: Point<f32>) {
+        let aabb = shape.compute_local_aabb();
+        assert!((aabb.mins - mins).norm() < 1.0e-4, "{:?}", aabb.mins);
+        assert!((aabb.maxs - maxs).norm() < 1.0e-4, "{:?}", aabb.maxs);
+    }
+
+    #[test]
+    fn margins_keep_the_shape_size() {
+        let rounded = rounded_cuboid(Cuboid::new(vector![0.5, 1.0, 2.0]), 0.04);
+        assert!(rounded.as_round_cuboid().is_some());
+        assert_aabb_eq(&rounded, point![-0.5, -1.0, -2.0], point![0.5, 1.0, 2.0]);
+
+        let rounded = rounded_cylinder(Cylinder::new(1.0, 0.5), 0.04);
+        assert_aabb_eq(&rounded, point![-0.5, -1.0, -0.5], point![0.5, 1.0, 0.5]);
+
+        let hull = ConvexPolyhedron::from_convex_hull(&[
+            point![0.0, 0.0, 0.0],
+            point![2.0, 0.0, 0.0],
+            point![0.0, 1.0, 0.0],
+            point![0.0, 0.0, 3.0],
+        ])
+        .unwrap();
+        let rounded = rounded_convex_polyhedron(hull, 0.04);
+        assert!(rounded.as_round_convex_polyhedron().is_some());
+        assert_aabb_eq(&rounded, point![0.0, 0.0, 0.0], point![2.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn margins_are_clamped_to_the_shape() {
+        let rounded = rounded_cuboid(Cuboid::new(vector![0.5, 0.01, 0.5]), 0.04);
+        let round_cuboid = rounded.as_round_cuboid().unwrap();
+        assert_eq!(round_cuboid.border_radius, 0.01);
+        assert_eq!(round_cuboid.inner_shape.half_extents.y, 0.0);
+        assert_aabb_eq(&rounded, point![-0.5, -0.01, -0.5], point![0.5, 0.01, 0.5]);
+    }
+
+    #[test]
+    fn cached_shapes_are_capped() {
+        let cache = SharedShapeCache::default();
+        let in_use: Vec<SharedShape> = (0..MAX_CACHED_SHAPES * 2)
+            .map(|i| {
+                let scale = vector![1.0 + i as f32, 1.0, 1.0];
+                cache.get_or_insert_with(scale, false, || SharedShape::ball(1.0))
+            })
+            .collect();
+        assert_eq!(in_use.len(), MAX_CACHED_SHAPES * 2);
+        assert_eq!(cache.shapes.borrow().len(), MAX_CACHED_SHAPES);
+    }
+
+    #[test]
+    fn unused_scales_are_evicted() {
+        let cache = SharedShapeCache::default();
+        let kept =
+            cache.get_or_insert_with(vector![1.0, 1.0, 1.0], false, || SharedShape::ball(1.0));
+        let mut animated = None;
+        for i in 1..100 {
+            let scale = vector![1.0 + i as f32 * 0.01, 1.0, 1.0];
+            // Like a collider, the owner drops its previous shape when it gets the new one.
+            animated = Some(cache.get_or_insert_with(scale, false, || SharedShape::ball(1.0)));
         }
+        // The shape replaced last is only dropped after its replacement was built.
+        assert!(animated.is_some());
+        assert_eq!(cache.shapes.borrow().len(), 3);
+        drop(kept);
+        cache.get_or_insert_with(vector![2.0, 2.0, 2.0], false, || SharedShape::ball(1.0));
+        assert_eq!(cache.shapes.borrow().len(), 2);
     }
 }
@@ -3,14 +3,16 @@ use std::{cell::RefCell, rc::Rc};
 use godot::prelude::{math::ApproxEq, *};
 use rapier3d::prelude::*;
 
-use crate::collision_object::RapierCollisionObject;
+use crate::{collision_object::RapierCollisionObject, contact_modifiers::SeparationRay};
 
-use super::RapierShape;
+use super::{RapierShape, SharedShapeCache};
 
 pub struct RapierSeparationRayShape {
     shape: Segment,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
+    slide_on_slope: bool,
 }
 
 impl RapierSeparationRayShape {
@@ -18,7 +20,9 @@ impl RapierSeparationRayShape {
         Self {
             shape: Segment::new(point![0.0, 0.0, 0.0], point![0.0, 0.0, 0.5]),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
+            slide_on_slope: false,
         }
     }
 }
@@ -30,7 +34,7 @@ impl RapierShape for RapierSeparationRayShape {
 
     fn data(&self) -> Variant {
         let length = (self.shape.b - self.shape.a).norm();
-        Variant::from(dict! {"length":length , "slide_on_slope" :false})
+        Variant::from(dict! {"length":length , "slide_on_slope" :self.slide_on_slope})
     }
 
     fn set_data(&mut self, data: Variant) {
@@ -41,17 +45,16 @@ impl RapierShape for RapierSeparationRayShape {
                         let cur_length = (self.shape.b - self.shape.a).norm();
                         if !length.approx_eq(&cur_length) {
                             self.shape.b = length * point![0.0, 0.0, 1.0];
-                            self.update_owners();
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
                 };
                 match d.get_or_nil("slide_on_slope").try_to::<bool>() {
                     Ok(value) => {
-                        if value {
-                            godot_warn!(
-                            "Godot Rapier doesn't support slide_on_slope for SeparationRayShape"
-                        );
+                        if value != self.slide_on_slope {
+                            self.slide_on_slope = value;
+                            self.shared_shapes.invalidate();
                         }
                     }
                     Err(e) => godot_error!("{:?}", e),
@@ -61,10 +64,14 @@ impl RapierShape for RapierSeparationRayShape {
         }
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
         SharedShape::new(self.shape.scaled(&scale))
     }
 
+    fn separation_ray(&self) -> Option<SeparationRay> {
+        Some(SeparationRay::new(self.slide_on_slope))
+    }
+
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_SEPARATION_RAY
     }
@@ -75,4 +82,7 @@ impl RapierShape for RapierSeparationRayShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
 }
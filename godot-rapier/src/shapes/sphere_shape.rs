@@ -5,11 +5,18 @@ use rapier3d::{parry::either::Either, prelude::*};
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{
+    data_subdivisions, round_shape_scale, rounded_convex_polyhedron, RapierShape, SharedShapeCache,
+    DEFAULT_SCALED_SUBDIVISIONS,
+};
 pub struct RapierSphereShape {
     shape: Ball,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
+    /// Subdivisions of the convex approximation used under non-uniform scale.
+    subdivisions: u32,
+    margin: f32,
 }
 
 impl RapierSphereShape {
@@ -17,36 +24,59 @@ impl RapierSphereShape {
         Self {
             shape: Ball::new(0.5),
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
+            subdivisions: DEFAULT_SCALED_SUBDIVISIONS,
+            margin: 0.0,
         }
     }
 }
 
 impl RapierShape for RapierSphereShape {
+    /// The radius, or the same Dictionary `set_data` accepts once subdivisions were set.
     fn data(&self) -> Variant {
-        Variant::from(self.shape.radius)
+        if self.subdivisions == DEFAULT_SCALED_SUBDIVISIONS {
+            return Variant::from(self.shape.radius);
+        }
+        Variant::from(dict! {"radius": self.shape.radius, "subdivisions": self.subdivisions})
     }
 
+    /// Accepts the radius, or a Dictionary with the `"radius"` and optional `"subdivisions"`.
     fn set_data(&mut self, data: Variant) {
-        match data.try_to::<f32>() {
+        let radius = if let Ok(d) = data.try_to::<Dictionary>() {
+            if let Some(subdivisions) = data_subdivisions(&d) {
+                if subdivisions != self.subdivisions {
+                    self.subdivisions = subdivisions;
+                    self.shared_shapes.invalidate();
+                }
+            }
+            d.get_or_nil("radius").try_to::<f32>()
+        } else {
+            data.try_to::<f32>()
+        };
+        match radius {
             Ok(radius) => {
                 if !radius.approx_eq(&self.shape.radius) {
                     self.shape.radius = radius;
-                    self.update_owners();
+                    self.shared_shapes.invalidate();
                 }
             }
             Err(err) => godot_error!("{:?}", err),
         };
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
-        self.shape.scaled(&scale, 3).map_or_else(
-            || SharedShape::new(self.shape),
-            |either| match either {
-                Either::Left(ball) => SharedShape::new(ball),
-                Either::Right(convex_poly) => SharedShape::new(convex_poly),
-            },
-        )
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+        self.shape
+            .scaled(&round_shape_scale(scale), self.subdivisions)
+            .map_or_else(
+                || SharedShape::new(self.shape),
+                |either| match either {
+                    Either::Left(ball) => SharedShape::new(ball),
+                    Either::Right(convex_poly) => {
+                        rounded_convex_polyhedron(convex_poly, self.margin)
+                    }
+                },
+            )
     }
 
     fn rid(&self) -> Rid {
@@ -59,8 +89,24 @@ impl RapierShape for RapierSphereShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
 
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_SPHERE
     }
+
+    /// A ball is already round, only its convex approximation under non-uniform scale is
+    /// rounded by the margin.
+    fn set_margin(&mut self, margin: f32) {
+        if !margin.approx_eq(&self.margin) {
+            self.margin = margin;
+            self.shared_shapes.invalidate();
+        }
+    }
+
+    fn margin(&self) -> f32 {
+        self.margin
+    }
 }
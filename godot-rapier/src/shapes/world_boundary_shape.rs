@@ -5,10 +5,13 @@ use rapier3d::prelude::*;
 
 use crate::collision_object::RapierCollisionObject;
 
-use super::RapierShape;
+use super::{RapierShape, SharedShapeCache};
 pub struct RapierWorldBoundaryShape {
     shape: HalfSpace,
+    /// Distance of the plane from the origin, along its normal.
+    d: f32,
     owners: Vec<Rc<RefCell<dyn RapierCollisionObject>>>,
+    shared_shapes: SharedShapeCache,
     rid: Rid,
 }
 
@@ -16,7 +19,9 @@ impl RapierWorldBoundaryShape {
     pub fn new(rid: Rid) -> Self {
         Self {
             shape: HalfSpace::new(UnitVector::new_normalize(vector![0.0, 1.0, 0.0])),
+            d: 0.0,
             owners: vec![],
+            shared_shapes: SharedShapeCache::default(),
             rid,
         }
     }
@@ -32,7 +37,7 @@ impl RapierShape for RapierWorldBoundaryShape {
             self.shape.normal.y,
             self.shape.normal.z,
         );
-        Variant::from(Plane::new(normal, 0.0))
+        Variant::from(Plane::new(normal, self.d))
     }
 
     fn set_data(&mut self, data: Variant) {
@@ -43,21 +48,17 @@ impl RapierShape for RapierWorldBoundaryShape {
                     plane.normal.y,
                     plane.normal.z
                 ]);
-                if normal != self.shape.normal {
+                if normal != self.shape.normal || plane.d != self.d {
                     self.shape = HalfSpace::new(normal);
-                    self.update_owners();
-                }
-                if plane.d != 0.0 {
-                    godot_warn!(
-                        "Godot Rapier doesn't support WorldBoundaryShapes not set in the origin"
-                    );
+                    self.d = plane.d;
+                    self.shared_shapes.invalidate();
                 }
             }
             Err(err) => godot_error!("{:?}", err),
         }
     }
 
-    fn shared_shape(&self, scale: Vector<f32>) -> SharedShape {
+    fn build_shared_shape(&self, scale: Vector<f32>) -> SharedShape {
         self.shape.scaled(&scale).map_or_else(
             || {
                 godot_error!("WorldBoundaryShape normal degenerated to zero");
@@ -67,6 +68,16 @@ impl RapierShape for RapierWorldBoundaryShape {
         )
     }
 
+    /// Rapier half spaces go through the origin, the plane distance moves the collider instead.
+    fn local_isometry(&self, scale: Vector<f32>) -> Isometry<f32> {
+        let point_on_plane = self.shape.normal.into_inner() * self.d;
+        Isometry::translation(
+            point_on_plane.x * scale.x,
+            point_on_plane.y * scale.y,
+            point_on_plane.z * scale.z,
+        )
+    }
+
     fn get_type(&self) -> godot::engine::physics_server_3d::ShapeType {
         godot::engine::physics_server_3d::ShapeType::SHAPE_WORLD_BOUNDARY
     }
@@ -77,4 +88,7 @@ impl RapierShape for RapierWorldBoundaryShape {
     fn owners_mut(&mut self) -> &mut Vec<Rc<RefCell<dyn RapierCollisionObject>>> {
         &mut self.owners
     }
+    fn shared_shape_cache(&self) -> &SharedShapeCache {
+        &self.shared_shapes
+    }
 }
@@ -10,17 +10,110 @@ use godot::{
     },
     prelude::*,
 };
-use rapier3d::prelude::*;
+use rapier3d::{
+    parry::{
+        bounding_volume::BoundingVolume,
+        query::{self, TOIStatus},
+    },
+    prelude::*,
+};
 
 use crate::{
-    area::RapierArea, body::RapierBody, collision_object::RapierCollisionObject,
-    conversions::IntoExt, direct_space_state_3d::RapierPhysicsDirectSpaceState3D,
+    area::RapierArea,
+    body::RapierBody,
+    collision_object::{owner_rid, owner_shape_index, owner_user_data, RapierCollisionObject},
+    contact_modifiers::{one_way, OneWayCollision, SeparationRay, SurfaceVelocity},
+    conversions::IntoExt,
+    direct_space_state_3d::RapierPhysicsDirectSpaceState3D,
+    physics_hooks::{ContactModifier, PairFilter, RapierPhysicsHooks},
 };
 
+/// Inertia matrix of `props` about `point`, by the parallel axis theorem. Adding up
+/// `MassProperties` is avoided, it shifts the inertia the wrong way.
+fn inertia_matrix_about(props: &MassProperties, point: &Point<f32>) -> Matrix<f32> {
+    let shift = point - props.local_com;
+    props.reconstruct_inertia_matrix()
+        + (Matrix::identity() * shift.norm_squared() - shift * shift.transpose()) * props.mass()
+}
+
+pub struct MotionCollision {
+    pub position: Point<Real>,
+    pub normal: Vector<Real>,
+    pub collider_velocity: Vector<Real>,
+    pub collider_angular_velocity: Vector<Real>,
+    pub depth: Real,
+    pub local_shape: usize,
+    pub collider: Rid,
+    pub collider_id: u64,
+    pub collider_shape: usize,
+}
+
+pub struct MotionResult {
+    pub travel: Vector<Real>,
+    pub remainder: Vector<Real>,
+    pub collision_safe_fraction: Real,
+    pub collision_unsafe_fraction: Real,
+    pub collisions: Vec<MotionCollision>,
+}
+
+/// A shape taking part in a motion test, with its world position and index in its owner.
+struct MotionPart<'a> {
+    index: usize,
+    position: Isometry<Real>,
+    shape: &'a dyn Shape,
+    separation_ray: Option<SeparationRay>,
+}
+
+/// A shape of the body being moved: index, position at the start of the motion, shape and
+/// separation ray behaviour.
+type MotionShape = (usize, Isometry<Real>, SharedShape, Option<SeparationRay>);
+
+/// Colliders added, changed or removed since the query pipeline was last updated.
+#[derive(Default)]
+struct PendingQueryUpdates {
+    modified: Vec<ColliderHandle>,
+    removed: Vec<ColliderHandle>,
+}
+
+/// The space parameters deciding when bodies fall asleep.
+#[derive(Clone, Copy)]
+struct SleepParameters {
+    linear_threshold: f32,
+    angular_threshold: f32,
+    time_until_sleep: f32,
+}
+
+impl Default for SleepParameters {
+    fn default() -> Self {
+        Self {
+            linear_threshold: DEFAULT_SLEEP_THRESHOLD_LINEAR,
+            angular_threshold: DEFAULT_SLEEP_THRESHOLD_ANGULAR,
+            time_until_sleep: DEFAULT_TIME_TO_SLEEP,
+        }
+    }
+}
+
+impl SleepParameters {
+    /// Sets the thresholds of `activation`, keeping whether the body sleeps. Rapier never puts
+    /// to sleep bodies with negative thresholds.
+    fn apply(self, activation: &mut RigidBodyActivation, can_sleep: bool) {
+        if can_sleep {
+            activation.linear_threshold = self.linear_threshold;
+            activation.angular_threshold = self.angular_threshold;
+        } else {
+            activation.linear_threshold = -1.0;
+            activation.angular_threshold = -1.0;
+            activation.time_since_can_sleep = 0.0;
+        }
+        activation.time_until_sleep = self.time_until_sleep;
+    }
+}
+
 pub struct RapierSpace {
     rid: Rid,
     bodies: HashMap<RigidBodyHandle, Rc<RefCell<RapierBody>>>,
-    areas: HashMap<ColliderHandle, Rc<RefCell<RapierArea>>>,
+    body_handles: HashMap<Rid, RigidBodyHandle>,
+    areas: HashMap<Rid, Rc<RefCell<RapierArea>>>,
     default_area: Option<Rc<RefCell<RapierArea>>>,
 
     rigid_body_set: RigidBodySet,
@@ -34,11 +127,16 @@ pub struct RapierSpace {
     impulse_joint_set: ImpulseJointSet,
     multibody_joint_set: MultibodyJointSet,
     ccd_solver: CCDSolver,
-    physics_hooks: (),
+    physics_hooks: RapierPhysicsHooks,
     event_handler: (),
+    /// Bounding volumes of the colliders for the motion tests. The step keeps it up to date, the
+    /// colliders changed in between are applied on the next query.
+    query_pipeline: RefCell<QueryPipeline>,
+    pending_query_updates: RefCell<PendingQueryUpdates>,
 
     direct_state: Option<Gd<RapierPhysicsDirectSpaceState3D>>,
     has_stepped: bool,
+    sleep_parameters: SleepParameters,
 }
 
 impl Drop for RapierSpace {
@@ -55,13 +153,17 @@ const DEFAULT_CONTACT_MAX_ALLOWED_PENETRATION: f32 = 0.01;
 const DEFAULT_CONTACT_DEFAULT_BIAS: f32 = 0.8;
 const DEFAULT_SLEEP_THRESHOLD_LINEAR: f32 = 0.1;
 const DEFAULT_SLEEP_THRESHOLD_ANGULAR: f32 = 8.0 * std::f32::consts::PI / 180.0;
+const DEFAULT_TIME_TO_SLEEP: f32 = 0.5;
 const DEFAULT_SOLVER_ITERATIONS: u32 = 8;
+const MOTION_RECOVERY_ITERATIONS: usize = 4;
+const MOTION_RECOVERY_FACTOR: Real = 0.4;
 
 impl RapierSpace {
     pub fn new(rid: Rid) -> Self {
         Self {
             rid,
             bodies: HashMap::default(),
+            body_handles: HashMap::default(),
             areas: HashMap::default(),
             default_area: None,
             rigid_body_set: RigidBodySet::default(),
@@ -75,10 +177,13 @@ impl RapierSpace {
             impulse_joint_set: ImpulseJointSet::default(),
             multibody_joint_set: MultibodyJointSet::default(),
             ccd_solver: CCDSolver::default(),
-            physics_hooks: Default::default(),
+            physics_hooks: RapierPhysicsHooks::default(),
             event_handler: Default::default(),
+            query_pipeline: RefCell::default(),
+            pending_query_updates: RefCell::default(),
             direct_state: None,
             has_stepped: false,
+            sleep_parameters: SleepParameters::default(),
         }
     }
 
@@ -94,14 +199,31 @@ impl RapierSpace {
         }
     }
 
-    fn pre_step(&self) {
-        for body in self.bodies.values() {
-            body.borrow_mut().pre_step(self.integration_parameters.dt);
+    /// Steps `space` in phases, without keeping it borrowed while its bodies update it. Like in
+    /// Godot, the force integration callbacks run first, then the bodies integrate gravity and
+    /// damping on top of the velocities the callbacks set, and the solver runs.
+    pub fn step(space: &Rc<RefCell<Self>>) {
+        let (bodies, step) = {
+            let space = space.borrow();
+            let bodies: Vec<Rc<RefCell<RapierBody>>> = space.bodies.values().cloned().collect();
+            (bodies, space.integration_parameters.dt)
+        };
+        for body in &bodies {
+            let force_integration_call = body.borrow().force_integration_call();
+            if let Some((callback, args)) = force_integration_call {
+                callback.callv(args);
+            }
+        }
+        for body in &bodies {
+            body.borrow_mut().pre_step(step);
         }
+        space.borrow_mut().solve();
     }
 
-    pub fn step(&mut self) {
-        self.pre_step();
+    /// Steps the simulation, returns the bodies that were active during the step with whether
+    /// they are now asleep.
+    fn step_simulation(&mut self) -> Vec<(RigidBodyHandle, bool)> {
+        let previously_active = self.island_manager.active_dynamic_bodies().to_vec();
         self.physics_pipeline.step(
             &self.gravity,
             &self.integration_parameters,
@@ -113,70 +235,628 @@ impl RapierSpace {
             &mut self.impulse_joint_set,
             &mut self.multibody_joint_set,
             &mut self.ccd_solver,
-            None,
+            Some(self.query_pipeline.get_mut()),
             &self.physics_hooks,
             &self.event_handler,
         );
+        // The step applied the collider changes to the query pipeline.
+        *self.pending_query_updates.get_mut() = PendingQueryUpdates::default();
+        // Rapier keeps forces until they are reset. Forces applied once only last one step, and
+        // the constant ones are applied again before the next.
+        let pushed: Vec<RigidBodyHandle> = self
+            .rigid_body_set
+            .iter()
+            .filter(|(_, body)| {
+                body.user_force() != Vector::zeros() || body.user_torque() != Vector::zeros()
+            })
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in pushed {
+            if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                body.reset_forces(false);
+                body.reset_torques(false);
+            }
+        }
+        // Only the bodies active before or after the step can have moved, fallen asleep or been
+        // woken up, the others need no sync.
+        previously_active
+            .iter()
+            .chain(self.island_manager.active_dynamic_bodies())
+            .filter_map(|handle| {
+                let rigid_body = self.rigid_body_set.get(*handle)?;
+                Some((*handle, rigid_body.is_sleeping()))
+            })
+            .collect()
+    }
+
+    fn solve(&mut self) {
+        for (handle, sleeping) in self.step_simulation() {
+            if let Some(body) = self.bodies.get(&handle) {
+                body.borrow_mut().update_after_step(sleeping);
+            }
+        }
         self.has_stepped = true;
     }
-    pub fn set_area_collision_group(
+    fn update_active_hooks(&mut self, handle: ColliderHandle) {
+        if let Some(collider) = self.collider_set.get_mut(handle) {
+            let active_hooks = self
+                .physics_hooks
+                .active_hooks(handle, owner_rid(collider.user_data));
+            collider.set_active_hooks(active_hooks);
+        }
+    }
+
+    /// Updates the active hooks of the colliders of the body `owner`, after its pairs changed.
+    fn update_owner_active_hooks(&mut self, owner: Rid) {
+        let Some(colliders) = self
+            .body_handles
+            .get(&owner)
+            .and_then(|handle| self.rigid_body_set.get(*handle))
+            .map(|body| body.colliders().to_vec())
+        else {
+            return;
+        };
+        for handle in colliders {
+            self.update_active_hooks(handle);
+        }
+    }
+
+    fn update_all_active_hooks(&mut self) {
+        for (handle, collider) in self.collider_set.iter_mut() {
+            let active_hooks = self
+                .physics_hooks
+                .active_hooks(handle, owner_rid(collider.user_data));
+            collider.set_active_hooks(active_hooks);
+        }
+    }
+
+    pub fn add_collision_exception(&mut self, owner: Rid, excepted: Rid) {
+        self.physics_hooks.exclude_pair(owner, excepted);
+        self.update_owner_active_hooks(owner);
+        self.update_owner_active_hooks(excepted);
+    }
+
+    pub fn remove_collision_exception(&mut self, owner: Rid, excepted: Rid) {
+        self.physics_hooks.include_pair(owner, excepted);
+        self.update_owner_active_hooks(owner);
+        self.update_owner_active_hooks(excepted);
+    }
+
+    /// Disables or enables again the collisions between the bodies `a` and `b` of `joint`.
+    pub fn set_joint_collisions_disabled(&mut self, joint: Rid, a: Rid, b: Rid, disabled: bool) {
+        if disabled {
+            self.physics_hooks.exclude_joint_pair(joint, a, b);
+        } else {
+            self.physics_hooks.include_joint_pair(joint, a, b);
+        }
+        self.update_owner_active_hooks(a);
+        self.update_owner_active_hooks(b);
+    }
+
+    pub fn add_pair_filter(&mut self, filter: Box<dyn PairFilter>) {
+        self.physics_hooks.add_pair_filter(filter);
+        self.update_all_active_hooks();
+    }
+
+    pub fn set_contact_modifier<M: ContactModifier>(
         &mut self,
         handle: ColliderHandle,
-        collision_layer: u32,
-        collision_mask: u32,
+        modifier: M,
+    ) {
+        self.physics_hooks.set_contact_modifier(handle, modifier);
+        self.update_active_hooks(handle);
+    }
+
+    pub fn remove_contact_modifier<M: ContactModifier>(&mut self, handle: ColliderHandle) {
+        self.physics_hooks.remove_contact_modifier::<M>(handle);
+        self.update_active_hooks(handle);
+    }
+
+    pub fn set_one_way_collision(
+        &mut self,
+        handle: ColliderHandle,
+        direction: Option<Vector<Real>>,
     ) {
-        if let Some(area_collider) = self.collider_set.get_mut(handle) {
-            area_collider.set_collision_groups(InteractionGroups::new(
-                Group::from(collision_layer),
-                Group::from(collision_mask),
-            ));
+        if let Some(direction) = direction {
+            self.set_contact_modifier(handle, OneWayCollision::new(direction));
+        } else {
+            self.remove_contact_modifier::<OneWayCollision>(handle);
         }
     }
 
-    pub fn set_body_collision_group(
+    pub fn set_separation_ray(&mut self, handle: ColliderHandle, ray: Option<SeparationRay>) {
+        if let Some(ray) = ray {
+            self.set_contact_modifier(handle, ray);
+        } else {
+            self.remove_contact_modifier::<SeparationRay>(handle);
+        }
+    }
+
+    pub fn set_surface_velocity(
         &mut self,
         handle: RigidBodyHandle,
-        collision_layer: u32,
-        collision_mask: u32,
+        linear_velocity: Vector<Real>,
+        angular_velocity: Vector<Real>,
     ) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                collider.set_collision_groups(InteractionGroups::new(
-                    Group::from(collision_layer),
-                    Group::from(collision_mask),
-                ));
+        let Some(colliders) = self
+            .rigid_body_set
+            .get(handle)
+            .map(|body| body.colliders().to_vec())
+        else {
+            return;
+        };
+        for collider in colliders {
+            if linear_velocity == Vector::zeros() && angular_velocity == Vector::zeros() {
+                self.remove_contact_modifier::<SurfaceVelocity>(collider);
+            } else {
+                self.set_contact_modifier(
+                    collider,
+                    SurfaceVelocity::new(linear_velocity, angular_velocity),
+                );
             }
         }
     }
 
-    pub fn set_area_isometry(&mut self, handle: ColliderHandle, isometry: Isometry<f32>) {
-        if let Some(area_collider) = self.collider_set.get_mut(handle) {
-            area_collider.set_position(isometry);
+    /// Rid and shape index of the collision object owning a collider.
+    pub fn collider_owner(&self, handle: ColliderHandle) -> Option<(Rid, usize)> {
+        let collider = self.collider_set.get(handle)?;
+        Some((
+            owner_rid(collider.user_data),
+            owner_shape_index(collider.user_data),
+        ))
+    }
+
+    fn one_way_direction(&self, collider: &Collider) -> Option<Vector<Real>> {
+        let body = self.bodies.get(&collider.parent()?)?;
+        let direction = body
+            .borrow()
+            .one_way_direction(owner_shape_index(collider.user_data));
+        direction
+    }
+
+    fn collider_parts(collider: &Collider) -> Vec<MotionPart<'_>> {
+        collider.shape().as_compound().map_or_else(
+            || {
+                vec![MotionPart {
+                    index: 0,
+                    position: *collider.position(),
+                    shape: collider.shape(),
+                    separation_ray: None,
+                }]
+            },
+            |compound| {
+                compound
+                    .shapes()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (isometry, shape))| MotionPart {
+                        index,
+                        position: collider.position() * isometry,
+                        shape: &**shape,
+                        separation_ray: None,
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    fn offset_parts<'a>(shapes: &'a [MotionShape], offset: &Vector<Real>) -> Vec<MotionPart<'a>> {
+        shapes
+            .iter()
+            .map(|(index, position, shape, separation_ray)| MotionPart {
+                index: *index,
+                position: Translation::from(*offset) * position,
+                shape: &**shape,
+                separation_ray: *separation_ray,
+            })
+            .collect()
+    }
+
+    /// Normal, pointing from `body_part` into the other shape, and penetration depth of a contact
+    /// of the moving body. Separation rays measure them along their ray, `None` if the ray
+    /// doesn't go through the other shape.
+    fn motion_penetration(
+        body_part: &MotionPart,
+        contact: &query::Contact,
+        margin: Real,
+    ) -> Option<(Vector<Real>, Real)> {
+        let normal = contact.normal1.into_inner();
+        let depth = margin - contact.dist;
+        body_part
+            .separation_ray
+            .map_or(Some((normal, depth)), |ray| {
+                ray.resolve(
+                    &SeparationRay::direction(&body_part.position),
+                    &normal,
+                    depth,
+                )
+            })
+    }
+
+    /// Applies the collider changes made since the last step or query to the query pipeline.
+    fn update_query_pipeline(&self) {
+        let mut pending = self.pending_query_updates.borrow_mut();
+        if pending.modified.is_empty() && pending.removed.is_empty() {
+            return;
         }
+        self.query_pipeline.borrow_mut().update_incremental(
+            &self.collider_set,
+            &pending.modified,
+            &pending.removed,
+            true,
+        );
+        *pending = PendingQueryUpdates::default();
     }
 
-    pub fn update_area_shape(&mut self, handle: ColliderHandle, shape: Option<SharedShape>) {
-        if let Some(area_collider) = self.collider_set.get_mut(handle) {
-            match shape {
-                Some(shape) => area_collider.set_shape(shape),
-                None => area_collider.set_enabled(false),
+    fn motion_candidates(&self, owner: Rid, collision_mask: u32, aabb: &Aabb) -> Vec<&Collider> {
+        self.update_query_pipeline();
+        let mut candidates = vec![];
+        self.query_pipeline
+            .borrow()
+            .colliders_with_aabb_intersecting_aabb(aabb, |handle| {
+                if let Some(collider) = self.collider_set.get(*handle) {
+                    let other = owner_rid(collider.user_data);
+                    if collider.is_enabled()
+                        && !collider.is_sensor()
+                        && other != owner
+                        && collider.collision_groups().memberships.bits() & collision_mask != 0
+                        && !self.physics_hooks.is_pair_excluded(owner, other)
+                    {
+                        candidates.push(collider);
+                    }
+                }
+                true
+            });
+        candidates
+    }
+
+    fn one_way_allows(&self, collider: &Collider, normal: &Vector<Real>) -> bool {
+        let Some(direction) = self.one_way_direction(collider) else {
+            return true;
+        };
+        let local_normal = collider.position().inverse_transform_vector(normal);
+        one_way::allows_normal(&direction, &local_normal)
+    }
+
+    fn motion_collision(
+        &self,
+        collider: &Collider,
+        body_part: &MotionPart,
+        contact: &query::Contact,
+    ) -> MotionCollision {
+        let (normal, depth) = Self::motion_penetration(body_part, contact, 0.0).unwrap_or_default();
+        let mut collision = MotionCollision {
+            position: contact.point2,
+            normal: -normal,
+            collider_velocity: Vector::zeros(),
+            collider_angular_velocity: Vector::zeros(),
+            depth: depth.max(0.0),
+            local_shape: body_part.index,
+            collider: owner_rid(collider.user_data),
+            collider_id: 0,
+            collider_shape: owner_shape_index(collider.user_data),
+        };
+        if let Some(parent) = collider.parent() {
+            let mut surface_velocity = None;
+            if let Some(body) = self.bodies.get(&parent) {
+                let body = body.borrow();
+                collision.collider_id = body.instance_id().unwrap_or_default();
+                surface_velocity = body.surface_velocity();
+            }
+            if let Some(rigid_body) = self.rigid_body_set.get(parent) {
+                collision.collider_velocity = rigid_body.velocity_at_point(&contact.point2);
+                collision.collider_angular_velocity = *rigid_body.angvel();
+                if let Some(surface_velocity) = surface_velocity {
+                    collision.collider_velocity += surface_velocity
+                        .velocity_at_point(rigid_body.center_of_mass(), &contact.point2);
+                    collision.collider_angular_velocity += surface_velocity.angular_velocity();
+                }
             }
         }
+        collision
     }
-    pub fn update_body_shape(&mut self, handle: RigidBodyHandle, shape: Option<SharedShape>) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                match shape {
-                    Some(shape) => collider.set_shape(shape),
-                    None => collider.set_enabled(false),
+
+    /// Moves the shapes of `body` from `from` along `motion` and reports what they hit on the
+    /// way, without moving the body itself. Separation rays don't stop the motion, they only
+    /// take part in the recovery and the reported collisions if `collide_separation_ray` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn body_test_motion(
+        &self,
+        body: &RapierBody,
+        from: Isometry<Real>,
+        motion: Vector<Real>,
+        margin: Real,
+        max_collisions: usize,
+        collide_separation_ray: bool,
+        recovery_as_collision: bool,
+    ) -> MotionResult {
+        let shapes: Vec<MotionShape> = body
+            .shapes()
+            .iter()
+            .enumerate()
+            .filter(|(_, shape_instance)| shape_instance.is_enabled())
+            .map(|(index, shape_instance)| {
+                (
+                    index,
+                    from * body.collider_position(shape_instance),
+                    body.instance_shared_shape(shape_instance),
+                    shape_instance.separation_ray(),
+                )
+            })
+            .filter(|(_, _, _, separation_ray)| collide_separation_ray || separation_ray.is_none())
+            .collect();
+        self.shapes_test_motion(
+            body.rid(),
+            body.get_collision_mask(),
+            &shapes,
+            motion,
+            margin,
+            max_collisions,
+            recovery_as_collision,
+        )
+    }
+
+    /// Motion test of the `shapes` of `owner`, placed at the start of the motion.
+    #[allow(clippy::too_many_arguments)]
+    fn shapes_test_motion(
+        &self,
+        owner: Rid,
+        collision_mask: u32,
+        shapes: &[MotionShape],
+        motion: Vector<Real>,
+        margin: Real,
+        max_collisions: usize,
+        recovery_as_collision: bool,
+    ) -> MotionResult {
+        let mut aabb = Aabb::new_invalid();
+        for part in Self::offset_parts(shapes, &Vector::zeros())
+            .iter()
+            .chain(Self::offset_parts(shapes, &motion).iter())
+        {
+            aabb.merge(&part.shape.compute_aabb(&part.position));
+        }
+        let candidates =
+            self.motion_candidates(owner, collision_mask, &aabb.loosened(margin * 2.0));
+        let candidate_parts: Vec<(&Collider, MotionPart)> = candidates
+            .iter()
+            .flat_map(|collider| {
+                Self::collider_parts(collider)
+                    .into_iter()
+                    .map(move |part| (*collider, part))
+            })
+            .collect();
+
+        // Push the shapes out of anything they already overlap before sweeping them.
+        let mut recovery = Vector::zeros();
+        for _ in 0..MOTION_RECOVERY_ITERATIONS {
+            let mut recovery_step = Vector::zeros();
+            for body_part in Self::offset_parts(shapes, &recovery) {
+                for (collider, part) in &candidate_parts {
+                    if let Ok(Some(contact)) = query::contact(
+                        &body_part.position,
+                        body_part.shape,
+                        &part.position,
+                        part.shape,
+                        margin,
+                    ) {
+                        if !self.one_way_allows(collider, &contact.normal2) {
+                            continue;
+                        }
+                        if let Some((normal, depth)) =
+                            Self::motion_penetration(&body_part, &contact, margin)
+                        {
+                            recovery_step -= normal * depth * MOTION_RECOVERY_FACTOR;
+                        }
+                    }
+                }
+            }
+            if recovery_step == Vector::zeros() {
+                break;
+            }
+            recovery += recovery_step;
+        }
+
+        let mut safe_fraction: Real = 1.0;
+        let mut unsafe_fraction: Real = 1.0;
+        let motion_length = motion.norm();
+        if motion_length > 0.0 {
+            for body_part in Self::offset_parts(shapes, &recovery) {
+                if body_part.separation_ray.is_some() {
+                    continue;
+                }
+                for (collider, part) in &candidate_parts {
+                    let one_way_direction = self.one_way_direction(collider);
+                    if let Some(direction) = one_way_direction {
+                        // Shapes moving along the one-way direction always go through.
+                        if motion.dot(&(collider.position().rotation * direction)) >= 0.0 {
+                            continue;
+                        }
+                    }
+                    let Ok(Some(toi)) = query::time_of_impact(
+                        &body_part.position,
+                        &motion,
+                        body_part.shape,
+                        &part.position,
+                        &Vector::zeros(),
+                        part.shape,
+                        unsafe_fraction,
+                        true,
+                    ) else {
+                        continue;
+                    };
+                    if one_way_direction.is_some()
+                        && (toi.status == TOIStatus::Penetrating
+                            || !self.one_way_allows(
+                                collider,
+                                &(part.position.rotation * toi.normal2.into_inner()),
+                            ))
+                    {
+                        continue;
+                    }
+                    if toi.toi < unsafe_fraction {
+                        unsafe_fraction = toi.toi;
+                        safe_fraction = (toi.toi - margin / motion_length).max(0.0);
+                    }
+                }
+            }
+        }
+
+        let travel = recovery + motion * safe_fraction;
+        let mut collisions = Vec::new();
+        if safe_fraction < 1.0 || (recovery_as_collision && recovery != Vector::zeros()) {
+            for body_part in Self::offset_parts(shapes, &travel) {
+                for (collider, part) in &candidate_parts {
+                    if let Ok(Some(contact)) = query::contact(
+                        &body_part.position,
+                        body_part.shape,
+                        &part.position,
+                        part.shape,
+                        margin * 2.0,
+                    ) {
+                        if self.one_way_allows(collider, &contact.normal2)
+                            && Self::motion_penetration(&body_part, &contact, 0.0).is_some()
+                        {
+                            collisions.push(self.motion_collision(collider, &body_part, &contact));
+                        }
+                    }
+                }
+            }
+            collisions.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+            collisions.truncate(max_collisions);
+        }
+
+        MotionResult {
+            travel,
+            remainder: motion * (1.0 - safe_fraction),
+            collision_safe_fraction: safe_fraction,
+            collision_unsafe_fraction: unsafe_fraction,
+            collisions,
+        }
+    }
+
+    pub fn add_body_collider(
+        &mut self,
+        handle: RigidBodyHandle,
+        collider: Collider,
+    ) -> ColliderHandle {
+        let collider_handle =
+            self.collider_set
+                .insert_with_parent(collider, handle, &mut self.rigid_body_set);
+        self.pending_query_updates
+            .get_mut()
+            .modified
+            .push(collider_handle);
+        self.update_active_hooks(collider_handle);
+        collider_handle
+    }
+
+    pub fn add_area_collider(&mut self, mut collider: Collider) -> ColliderHandle {
+        collider.set_sensor(true);
+        let collider_handle = self.collider_set.insert(collider);
+        self.pending_query_updates
+            .get_mut()
+            .modified
+            .push(collider_handle);
+        self.update_active_hooks(collider_handle);
+        collider_handle
+    }
+
+    pub fn remove_collider(&mut self, handle: ColliderHandle) {
+        self.physics_hooks.remove_collider(handle);
+        self.pending_query_updates.get_mut().removed.push(handle);
+        self.collider_set.remove(
+            handle,
+            &mut self.island_manager,
+            &mut self.rigid_body_set,
+            true,
+        );
+    }
+
+    /// Replaces the geometry of a collider, keeping its handle and material.
+    pub fn update_collider(&mut self, handle: ColliderHandle, collider: &Collider) {
+        if let Some(current) = self.collider_set.get_mut(handle) {
+            self.pending_query_updates.get_mut().modified.push(handle);
+            current.set_shape(collider.shared_shape().clone());
+            if current.parent().is_some() {
+                current.set_position_wrt_parent(*collider.position());
+            } else {
+                current.set_position(*collider.position());
+            }
+            current.set_enabled(collider.is_enabled());
+            current.user_data = collider.user_data;
+        }
+    }
+
+    /// Stores the current shape index of each collider of `owner` after shapes were inserted
+    /// or removed.
+    pub fn reindex_colliders(&mut self, owner: Rid, handles: &[ColliderHandle]) {
+        for (idx, handle) in handles.iter().enumerate() {
+            if let Some(collider) = self.collider_set.get_mut(*handle) {
+                collider.user_data = owner_user_data(owner, idx);
+            }
+        }
+    }
+
+    pub fn set_collider_position(&mut self, handle: ColliderHandle, isometry: Isometry<f32>) {
+        if let Some(collider) = self.collider_set.get_mut(handle) {
+            self.pending_query_updates.get_mut().modified.push(handle);
+            collider.set_position(isometry);
+        }
+    }
+
+    pub fn set_area_collision_group(
+        &mut self,
+        handles: &[ColliderHandle],
+        collision_layer: u32,
+        collision_mask: u32,
+    ) {
+        for handle in handles {
+            if let Some(area_collider) = self.collider_set.get_mut(*handle) {
+                area_collider.set_collision_groups(InteractionGroups::new(
+                    Group::from(collision_layer),
+                    Group::from(collision_mask),
+                ));
+            }
+        }
+    }
+
+    pub fn set_body_collision_group(
+        &mut self,
+        handle: RigidBodyHandle,
+        collision_layer: u32,
+        collision_mask: u32,
+    ) {
+        if let Some(body) = self.rigid_body_set.get(handle) {
+            for collider in body.colliders() {
+                if let Some(collider) = self.collider_set.get_mut(*collider) {
+                    collider.set_collision_groups(InteractionGroups::new(
+                        Group::from(collision_layer),
+                        Group::from(collision_mask),
+                    ));
                 }
             }
         }
     }
 
+    /// Changes the type of a body, stopping it first unless it stays dynamic. Rigid linear bodies
+    /// cannot rotate.
     pub fn set_body_mode(&mut self, handle: RigidBodyHandle, mode: BodyMode) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_body_type(mode.into_ext(), true);
+            let body_type: RigidBodyType = mode.into_ext();
+            if body_type != RigidBodyType::Dynamic {
+                // Kinematic bodies ignore the velocities they are given, so they have to be
+                // cleared while the body is still dynamic.
+                body.set_linvel(Vector::zeros(), false);
+                body.set_angvel(Vector::zeros(), false);
+                body.reset_forces(false);
+                body.reset_torques(false);
+            }
+            body.set_body_type(body_type, true);
+            let can_rotate = mode != BodyMode::BODY_MODE_RIGID_LINEAR;
+            body.set_enabled_rotations(can_rotate, can_rotate, can_rotate, true);
+            if body.is_kinematic() {
+                body.set_next_kinematic_position(*body.position());
+            }
         }
     }
 
@@ -227,96 +907,150 @@ impl RapierSpace {
         }
     }
 
-    pub fn move_kinematic(&mut self, handle: RigidBodyHandle, new_pos: Isometry<f32>) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_next_kinematic_position(new_pos);
-        }
+    /// Moves a kinematic body to `new_pos` over the next step. Rapier gives it the velocity of
+    /// that motion during the step, which drags the bodies resting on it along and stays readable
+    /// until the next step, dropping back to zero once the body is no longer moved.
+    ///
+    /// Returns whether the body moves, or stops moving, in this step.
+    pub fn move_kinematic(&mut self, handle: RigidBodyHandle, new_pos: Isometry<f32>) -> bool {
+        let Some(body) = self.rigid_body_set.get_mut(handle) else {
+            return false;
+        };
+        body.set_next_kinematic_position(new_pos);
+        *body.position() != new_pos || body.is_moving()
     }
 
     pub fn set_bounce(&mut self, handle: RigidBodyHandle, value: f32) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                collider.set_restitution(value);
+        if let Some(body) = self.rigid_body_set.get(handle) {
+            for collider in body.colliders() {
+                if let Some(collider) = self.collider_set.get_mut(*collider) {
+                    collider.set_restitution(value);
+                }
             }
         }
     }
     pub fn set_friction(&mut self, handle: RigidBodyHandle, value: f32) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                collider.set_friction(value);
+        if let Some(body) = self.rigid_body_set.get(handle) {
+            for collider in body.colliders() {
+                if let Some(collider) = self.collider_set.get_mut(*collider) {
+                    collider.set_friction(value);
+                }
             }
         }
     }
-    pub fn set_inertia(&mut self, handle: RigidBodyHandle, inertia: Vector3) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                let mp = collider.mass_properties();
-                collider.set_mass_properties(MassProperties::new(
-                    mp.local_com,
-                    body.mass(),
-                    inertia.into_ext(),
-                ));
+    /// Sets the mass properties of a body. The mass is spread over its enabled colliders in
+    /// proportion to their volume, disabled ones do not count, and the center of mass and
+    /// inertia that are not custom are derived from them. The colliders themselves get no
+    /// density, Rapier would add up their inertia wrongly.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn set_mass_properties(
+        &mut self,
+        handle: RigidBodyHandle,
+        mass: f32,
+        center_of_mass: Option<Vector3>,
+        inertia: Option<Vector3>,
+    ) {
+        let Some(body) = self.rigid_body_set.get_mut(handle) else {
+            return;
+        };
+        let mut parts = vec![];
+        for collider in body.colliders() {
+            let Some(collider) = self.collider_set.get_mut(*collider) else {
+                continue;
+            };
+            if collider.is_enabled() {
+                let position = collider
+                    .position_wrt_parent()
+                    .copied()
+                    .unwrap_or_else(Isometry::identity);
+                parts.push(
+                    collider
+                        .shape()
+                        .mass_properties(1.0)
+                        .transform_by(&position),
+                );
             }
+            collider.set_density(0.0);
         }
-    }
-    pub fn set_custom_center_of_mass(&mut self, handle: RigidBodyHandle, center_of_mass: Vector3) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                let mp = collider.mass_properties();
-                collider.set_mass_properties(MassProperties::new(
-                    center_of_mass.into_ext(),
-                    body.mass(),
-                    mp.principal_inertia(),
-                ));
-            }
+        // At a density of 1 the mass of a part is its volume. Shapes without volume, like planes,
+        // only get mass when no part has any.
+        let volume: f32 = parts.iter().map(MassProperties::mass).sum();
+        let part_count = parts.len() as f32;
+        for part in &mut parts {
+            let part_mass = if volume > 0.0 {
+                mass * part.mass() / volume
+            } else {
+                mass / part_count
+            };
+            part.set_mass(part_mass, true);
         }
-    }
-
-    pub fn set_mass(&mut self, handle: RigidBodyHandle, value: f32, custom_mass_props: bool) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            if let Some(collider) = self.collider_set.get_mut(body.colliders()[0]) {
-                if custom_mass_props {
-                    let mp = collider.mass_properties();
-                    collider.set_mass_properties(MassProperties::new(
-                        mp.local_com,
-                        value,
-                        mp.principal_inertia(),
-                    ));
+        let local_com = center_of_mass.map_or_else(
+            || {
+                let parts_mass: f32 = parts.iter().map(MassProperties::mass).sum();
+                if parts_mass > 0.0 {
+                    Point::from(
+                        parts
+                            .iter()
+                            .map(|part| part.local_com.coords * part.mass())
+                            .sum::<Vector<f32>>()
+                            / parts_mass,
+                    )
                 } else {
-                    collider.set_mass(value);
+                    Point::origin()
                 }
-            }
-        }
+            },
+            IntoExt::into_ext,
+        );
+        let mass_properties = if let Some(inertia) = inertia {
+            MassProperties::new(local_com, mass, inertia.into_ext())
+        } else {
+            let inertia = parts
+                .iter()
+                .map(|part| inertia_matrix_about(part, &local_com))
+                .sum();
+            MassProperties::with_inertia_matrix(local_com, mass, inertia)
+        };
+        body.set_additional_mass_properties(mass_properties, true);
     }
     pub fn set_gravity_scale(&mut self, handle: RigidBodyHandle, value: f32) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
             body.set_gravity_scale(value, true);
         }
     }
-    pub fn set_linear_damp(&mut self, handle: RigidBodyHandle, value: f32) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_linear_damping(value);
-        }
-    }
-    pub fn set_angular_damp(&mut self, handle: RigidBodyHandle, value: f32) {
-        if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_angular_damping(value);
-        }
-    }
     pub fn set_transform(&mut self, handle: RigidBodyHandle, value: Transform3D) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
             let (isometry, _) = value.into_ext();
             body.set_position(isometry, true);
         }
     }
-    pub fn set_linear_velocity(&mut self, handle: RigidBodyHandle, value: Vector<f32>) {
+    /// Sets the velocity of a body, waking it up with `wake_up`. The velocities the body
+    /// integrates itself every step must not wake it, it would then never fall asleep.
+    pub fn set_linear_velocity(
+        &mut self,
+        handle: RigidBodyHandle,
+        value: Vector<f32>,
+        wake_up: bool,
+    ) {
+        if let Some(body) = self.rigid_body_set.get_mut(handle) {
+            body.set_linvel(value, wake_up);
+        }
+    }
+    pub fn set_angular_velocity(
+        &mut self,
+        handle: RigidBodyHandle,
+        value: Vector<f32>,
+        wake_up: bool,
+    ) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_linvel(value, true);
+            body.set_angvel(value, wake_up);
         }
     }
-    pub fn set_angular_velocity(&mut self, handle: RigidBodyHandle, value: Vector<f32>) {
+    /// Applies `gravity` to a body over the next step, as a force so that the velocity Rapier
+    /// checks against the sleep thresholds before solving doesn't include it.
+    pub fn apply_gravity(&mut self, handle: RigidBodyHandle, gravity: Vector<f32>) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            body.set_angvel(value, true);
+            let mass = body.mass();
+            body.add_force(gravity * mass, false);
         }
     }
     pub fn set_is_sleeping(&mut self, handle: RigidBodyHandle, value: bool) {
@@ -330,15 +1064,18 @@ impl RapierSpace {
     }
     pub fn set_can_sleep(&mut self, handle: RigidBodyHandle, value: bool) {
         if let Some(body) = self.rigid_body_set.get_mut(handle) {
-            let act = body.activation_mut();
-            *act = if value {
-                if act.is_active() {
-                    RigidBodyActivation::active()
-                } else {
-                    RigidBodyActivation::inactive()
-                }
-            } else {
-                RigidBodyActivation::cannot_sleep()
+            self.sleep_parameters.apply(body.activation_mut(), value);
+            if !value {
+                body.wake_up(true);
+            }
+        }
+    }
+
+    fn update_sleep_parameters(&mut self) {
+        for (handle, body) in &self.bodies {
+            if let Some(rigid_body) = self.rigid_body_set.get_mut(*handle) {
+                self.sleep_parameters
+                    .apply(rigid_body.activation_mut(), body.borrow().can_sleep());
             }
         }
     }
@@ -358,6 +1095,37 @@ impl RapierSpace {
         self.rigid_body_set.get(handle)
     }
 
+    /// Inverse of the principal inertia of a body, Rapier only keeps its square root.
+    pub fn inverse_principal_inertia(&self, handle: RigidBodyHandle) -> Option<Vector<Real>> {
+        let inv_inertia_sqrt = self
+            .rigid_body_set
+            .get(handle)?
+            .mass_properties()
+            .local_mprops
+            .inv_principal_inertia_sqrt;
+        Some(inv_inertia_sqrt.component_mul(&inv_inertia_sqrt))
+    }
+
+    /// World space rotation of the principal inertia axes of a body.
+    pub fn principal_inertia_axes(&self, handle: RigidBodyHandle) -> Option<Rotation<Real>> {
+        let body = self.rigid_body_set.get(handle)?;
+        Some(
+            body.position().rotation
+                * body
+                    .mass_properties()
+                    .local_mprops
+                    .principal_inertia_local_frame,
+        )
+    }
+
+    /// World space inverse inertia tensor of a body, its inverse principal inertia rotated into
+    /// its principal axes.
+    pub fn inverse_inertia_tensor(&self, handle: RigidBodyHandle) -> Option<Matrix<Real>> {
+        let axes = self.principal_inertia_axes(handle)?.to_rotation_matrix();
+        let inv_inertia = self.inverse_principal_inertia(handle)?;
+        Some(axes * Matrix::from_diagonal(&inv_inertia) * axes.transpose())
+    }
+
     pub fn set_param(&mut self, param: SpaceParameter, value: f32) {
         // TODO
         match param {
@@ -386,22 +1154,16 @@ impl RapierSpace {
                 );
             }
             SpaceParameter::SPACE_PARAM_BODY_LINEAR_VELOCITY_SLEEP_THRESHOLD => {
-                godot_warn!(
-                    "Space-specific linear velocity sleep threshold is not supported by Godot Rapier. 
-                    Any such value will be ignored."
-                );
+                self.sleep_parameters.linear_threshold = value;
+                self.update_sleep_parameters();
             }
             SpaceParameter::SPACE_PARAM_BODY_ANGULAR_VELOCITY_SLEEP_THRESHOLD => {
-                godot_warn!(
-                    "Space-specific angular velocity sleep threshold is not supported by Godot Rapier. 
-                    Any such value will be ignored."
-                );
+                self.sleep_parameters.angular_threshold = value;
+                self.update_sleep_parameters();
             }
             SpaceParameter::SPACE_PARAM_BODY_TIME_TO_SLEEP => {
-                godot_warn!(
-                    "Space-specific body sleep time is not supported by Godot Rapier. 
-                    Any such value will be ignored."
-                );
+                self.sleep_parameters.time_until_sleep = value;
+                self.update_sleep_parameters();
             }
             SpaceParameter::SPACE_PARAM_SOLVER_ITERATIONS => {
                 godot_warn!(
@@ -425,43 +1187,46 @@ impl RapierSpace {
             }
             SpaceParameter::SPACE_PARAM_CONTACT_DEFAULT_BIAS => DEFAULT_CONTACT_DEFAULT_BIAS,
             SpaceParameter::SPACE_PARAM_BODY_LINEAR_VELOCITY_SLEEP_THRESHOLD => {
-                DEFAULT_SLEEP_THRESHOLD_LINEAR
+                self.sleep_parameters.linear_threshold
             }
             SpaceParameter::SPACE_PARAM_BODY_ANGULAR_VELOCITY_SLEEP_THRESHOLD => {
-                DEFAULT_SLEEP_THRESHOLD_ANGULAR
+                self.sleep_parameters.angular_threshold
+            }
+            SpaceParameter::SPACE_PARAM_BODY_TIME_TO_SLEEP => {
+                self.sleep_parameters.time_until_sleep
             }
-            SpaceParameter::SPACE_PARAM_BODY_TIME_TO_SLEEP => 0.5,
             SpaceParameter::SPACE_PARAM_SOLVER_ITERATIONS => DEFAULT_SOLVER_ITERATIONS as f32,
             _ => 0.0,
         }
     }
 
-    pub fn add_area(&mut self, area: &Rc<RefCell<RapierArea>>) -> ColliderHandle {
-        let area_borrow = area.borrow_mut();
-        let collider = area_borrow.build_collider().sensor(true);
-        let handle = self.collider_set.insert(collider);
-        self.areas.insert(handle, area.clone());
-        handle
+    pub fn add_area(&mut self, area: &Rc<RefCell<RapierArea>>) {
+        let rid = area.borrow().rid();
+        self.areas.insert(rid, area.clone());
     }
 
-    pub fn set_default_area(&mut self, area: Rc<RefCell<RapierArea>>) -> ColliderHandle {
-        let collider = area.borrow_mut().build_collider().sensor(true);
-        let handle = self.collider_set.insert(collider);
+    pub fn set_default_area(&mut self, area: Rc<RefCell<RapierArea>>) {
         self.default_area = Some(area);
-        handle
     }
 
-    pub fn remove_area(&mut self, handle: ColliderHandle) {
-        self.collider_set.remove(
-            handle,
-            &mut self.island_manager,
-            &mut self.rigid_body_set,
-            false,
-        );
-        self.areas.remove(&handle);
+    pub fn remove_area(&mut self, rid: Rid) {
+        self.areas.remove(&rid);
     }
 
     pub fn remove_body(&mut self, handle: RigidBodyHandle) {
+        let owner = self.rigid_body_set.get(handle).map(|body| {
+            for collider in body.colliders() {
+                self.physics_hooks.remove_collider(*collider);
+                self.pending_query_updates.get_mut().removed.push(*collider);
+            }
+            owner_rid(body.user_data)
+        });
+        if let Some(owner) = owner {
+            self.body_handles.remove(&owner);
+            for other in self.physics_hooks.clear_exclusions(owner) {
+                self.update_owner_active_hooks(other);
+            }
+        }
         self.rigid_body_set.remove(
             handle,
             &mut self.island_manager,
@@ -473,47 +1238,44 @@ impl RapierSpace {
         self.bodies.remove(&handle);
     }
 
+    /// Adds the rigid body of `body`, its colliders are added separately, one per shape.
     pub fn add_body(&mut self, body: &Rc<RefCell<RapierBody>>) -> RigidBodyHandle {
-        let b = body.borrow_mut();
-        let mut collider = b
-            .build_collider()
-            .restitution(b.bounce())
-            .friction(b.friction())
-            .mass(b.mass())
-            .build();
-
-        if b.has_custom_center_of_mass() || b.inertia() != Vector3::ZERO {
-            let mp = collider.mass_properties();
-            let center_of_mass = if b.has_custom_center_of_mass() {
-                b.custom_center_of_mass().into_ext()
-            } else {
-                mp.local_com
-            };
-            let inertia = if b.inertia() == Vector3::ZERO {
-                mp.principal_inertia()
-            } else {
-                b.inertia().into_ext()
-            };
-
-            collider.set_mass_properties(MassProperties::new(center_of_mass, b.mass(), inertia));
-        }
-
+        let b = body.borrow();
         let body_type = b.body_mode().into_ext();
+        // Static and kinematic velocities are surface velocities, they must not move the body.
+        let (linear_velocity, angular_velocity) = if b.is_rigid() {
+            (b.linear_velocity(), b.angular_velocity())
+        } else {
+            (Vector3::ZERO, Vector3::ZERO)
+        };
         let rigid_body = RigidBodyBuilder::new(body_type)
             .ccd_enabled(b.is_ccd_enabled())
-            .linear_damping(b.linear_damp())
-            .angular_damping(b.angular_damp())
             .gravity_scale(b.gravity_scale())
             .position(b.isometry())
-            .linvel(b.linear_velocity().into_ext())
-            .angvel(b.angular_velocity().into_ext())
+            .linvel(linear_velocity.into_ext())
+            .angvel(angular_velocity.into_ext())
             .can_sleep(b.can_sleep())
-            .sleeping(b.is_sleeping());
+            .sleeping(b.is_sleeping())
+            .locked_axes(if b.body_mode() == BodyMode::BODY_MODE_RIGID_LINEAR {
+                LockedAxes::ROTATION_LOCKED
+            } else {
+                LockedAxes::empty()
+            })
+            .user_data(owner_user_data(b.rid(), 0));
 
         let handle = self.rigid_body_set.insert(rigid_body);
-        self.collider_set
-            .insert_with_parent(collider, handle, &mut self.rigid_body_set);
+        if let Some(rigid_body) = self.rigid_body_set.get_mut(handle) {
+            self.sleep_parameters
+                .apply(rigid_body.activation_mut(), b.can_sleep());
+        }
         self.bodies.insert(handle, body.clone());
+        self.body_handles.insert(b.rid(), handle);
+        // The colliders of the body are added later with their hooks, only the ones of the
+        // bodies it excepts may change now.
+        for excepted in b.collision_exceptions() {
+            self.physics_hooks.exclude_pair(b.rid(), *excepted);
+            self.update_owner_active_hooks(*excepted);
+        }
         handle
     }
     pub fn remove_space_from_bodies_areas(&mut self) {
@@ -552,3 +1314,287 @@ impl RapierSpace {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARGIN: Real = 0.04;
+
+    /// Space with a fixed 20x1x20 floor, its top at y = 0.5, owned by `Rid::new(2)`.
+    fn space_with_floor() -> RapierSpace {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let floor = space.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .user_data(owner_user_data(Rid::new(2), 0))
+                .build(),
+        );
+        space.add_body_collider(
+            floor,
+            ColliderBuilder::cuboid(10.0, 0.5, 10.0)
+                .user_data(owner_user_data(Rid::new(2), 0))
+                .build(),
+        );
+        space
+    }
+
+    fn ball_test_motion(space: &RapierSpace, motion: Vector<Real>) -> MotionResult {
+        let shapes: Vec<MotionShape> = vec![(
+            0,
+            Isometry::translation(0.0, 2.0, 0.0),
+            SharedShape::ball(0.5),
+            None,
+        )];
+        space.shapes_test_motion(Rid::new(3), 1, &shapes, motion, MARGIN, 4, false)
+    }
+
+    #[test]
+    fn motion_is_blocked_by_the_floor() {
+        let space = space_with_floor();
+        let result = ball_test_motion(&space, vector![0.0, -3.0, 0.0]);
+        assert!((result.collision_unsafe_fraction - 1.0 / 3.0).abs() < 1.0e-3);
+        assert!((result.travel.y - (MARGIN - 1.0)).abs() < 1.0e-3);
+        assert!((result.remainder.y - (-2.0 - MARGIN)).abs() < 1.0e-3);
+        let collision = &result.collisions[0];
+        assert_eq!(collision.collider, Rid::new(2));
+        assert!((collision.normal - Vector::y()).norm() < 1.0e-3);
+    }
+
+    #[test]
+    fn free_motion_travels_all_the_way() {
+        let space = space_with_floor();
+        let motion = vector![3.0, 0.0, 0.0];
+        let result = ball_test_motion(&space, motion);
+        assert_eq!(result.collision_safe_fraction, 1.0);
+        assert_eq!(result.travel, motion);
+        assert!(result.collisions.is_empty());
+    }
+
+    #[test]
+    fn excluded_bodies_do_not_block_the_motion() {
+        let mut space = space_with_floor();
+        space.add_collision_exception(Rid::new(3), Rid::new(2));
+        let motion = vector![0.0, -3.0, 0.0];
+        let result = ball_test_motion(&space, motion);
+        assert_eq!(result.collision_safe_fraction, 1.0);
+        assert_eq!(result.travel, motion);
+        assert!(result.collisions.is_empty());
+    }
+
+    /// Dynamic body at (1, 2, 3) moving along x and spinning around y.
+    fn moving_body(space: &mut RapierSpace) -> RigidBodyHandle {
+        space.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(vector![1.0, 2.0, 3.0])
+                .linvel(vector![1.0, 0.0, 0.0])
+                .angvel(vector![0.0, 1.0, 0.0])
+                .build(),
+        )
+    }
+
+    #[test]
+    fn static_bodies_are_stopped() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let handle = moving_body(&mut space);
+        space.apply_central_force(handle, vector![0.0, 5.0, 0.0]);
+        space.set_body_mode(handle, BodyMode::BODY_MODE_STATIC);
+        let body = &space.rigid_body_set[handle];
+        assert!(body.is_fixed());
+        assert_eq!(*body.linvel(), Vector::zeros());
+        assert_eq!(*body.angvel(), Vector::zeros());
+        assert_eq!(body.user_force(), Vector::zeros());
+    }
+
+    #[test]
+    fn kinematic_bodies_are_stopped_in_place() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let handle = moving_body(&mut space);
+        space.set_body_mode(handle, BodyMode::BODY_MODE_KINEMATIC);
+        let body = &space.rigid_body_set[handle];
+        assert!(body.is_kinematic());
+        assert_eq!(*body.linvel(), Vector::zeros());
+        assert_eq!(*body.angvel(), Vector::zeros());
+        assert_eq!(body.next_position(), body.position());
+        assert_eq!(body.translation(), &vector![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn rigid_linear_bodies_keep_moving_without_rotating() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let handle = moving_body(&mut space);
+        space.set_body_mode(handle, BodyMode::BODY_MODE_RIGID_LINEAR);
+        let body = &space.rigid_body_set[handle];
+        assert!(body.is_dynamic());
+        assert_eq!(body.is_rotation_locked(), [true; 3]);
+        assert_eq!(*body.linvel(), vector![1.0, 0.0, 0.0]);
+
+        space.set_body_mode(handle, BodyMode::BODY_MODE_RIGID);
+        let body = &space.rigid_body_set[handle];
+        assert!(body.is_dynamic());
+        assert_eq!(body.is_rotation_locked(), [false; 3]);
+    }
+
+    #[test]
+    fn frozen_bodies_start_at_rest_when_rigid_again() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let handle = moving_body(&mut space);
+        space.set_body_mode(handle, BodyMode::BODY_MODE_KINEMATIC);
+        space.set_body_mode(handle, BodyMode::BODY_MODE_RIGID);
+        let body = &space.rigid_body_set[handle];
+        assert!(body.is_dynamic());
+        assert_eq!(*body.linvel(), Vector::zeros());
+        assert_eq!(*body.angvel(), Vector::zeros());
+    }
+
+    #[test]
+    fn mass_is_spread_over_colliders_by_volume() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let handle = space
+            .rigid_body_set
+            .insert(RigidBodyBuilder::dynamic().build());
+        space.add_body_collider(handle, ColliderBuilder::cuboid(1.0, 1.0, 1.0).build());
+        space.add_body_collider(
+            handle,
+            ColliderBuilder::cuboid(0.5, 0.5, 0.5)
+                .translation(vector![3.0, 0.0, 0.0])
+                .build(),
+        );
+        space.set_mass_properties(handle, 9.0, None, None);
+        let body = &mut space.rigid_body_set[handle];
+        body.recompute_mass_properties_from_colliders(&space.collider_set);
+        assert!((body.mass() - 9.0).abs() < 1.0e-4);
+        // The big cube is 8 times the volume of the small one, so it gets 8 of the 9 kg.
+        assert!((body.center_of_mass().x - 1.0 / 3.0).abs() < 1.0e-4);
+    }
+
+    /// Dynamic body at `position` whose mass properties are derived from `collider`.
+    fn body_with_mass(
+        space: &mut RapierSpace,
+        position: Isometry<Real>,
+        collider: Collider,
+        mass: Real,
+    ) -> RigidBodyHandle {
+        let handle = space
+            .rigid_body_set
+            .insert(RigidBodyBuilder::dynamic().position(position).build());
+        space.add_body_collider(handle, collider);
+        space.set_mass_properties(handle, mass, None, None);
+        space.rigid_body_set[handle].recompute_mass_properties_from_colliders(&space.collider_set);
+        handle
+    }
+
+    fn assert_matrix_eq(actual: &Matrix<Real>, expected: &Matrix<Real>) {
+        assert!(
+            (actual - expected).abs().max() < 1.0e-5,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn inverse_inertia_of_a_box() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        // A 2x4x6 box of 12 kg, its inertia is m / 12 * (b^2 + c^2) around each axis.
+        let collider = ColliderBuilder::cuboid(1.0, 2.0, 3.0).build();
+        let handle = body_with_mass(&mut space, Isometry::identity(), collider, 12.0);
+        let expected = Matrix::from_diagonal(&vector![1.0 / 52.0, 1.0 / 40.0, 1.0 / 20.0]);
+        assert_matrix_eq(&space.inverse_inertia_tensor(handle).unwrap(), &expected);
+
+        let mut inv_inertia = space.inverse_principal_inertia(handle).unwrap();
+        inv_inertia.as_mut_slice().sort_by(f32::total_cmp);
+        assert!((inv_inertia - vector![1.0 / 52.0, 1.0 / 40.0, 1.0 / 20.0]).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn inverse_inertia_of_a_sphere() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        // A sphere of radius 2 and 5 kg, its inertia is 2 / 5 * m * r^2 around every axis.
+        let collider = ColliderBuilder::ball(2.0).build();
+        let handle = body_with_mass(&mut space, Isometry::identity(), collider, 5.0);
+        let inv_inertia = space.inverse_principal_inertia(handle).unwrap();
+        assert!((inv_inertia - Vector::repeat(1.0 / 8.0)).norm() < 1.0e-5);
+        assert_matrix_eq(
+            &space.inverse_inertia_tensor(handle).unwrap(),
+            &(Matrix::identity() / 8.0),
+        );
+    }
+
+    #[test]
+    fn inverse_inertia_tensor_follows_the_body_rotation() {
+        let mut space = RapierSpace::new(Rid::new(1));
+        let rotation = Rotation::from_axis_angle(&Vector::x_axis(), 0.3)
+            * Rotation::from_axis_angle(&Vector::z_axis(), 0.7);
+        let position = Isometry::from_parts(vector![1.0, 2.0, 3.0].into(), rotation);
+        let collider = ColliderBuilder::cuboid(1.0, 2.0, 3.0).build();
+        let handle = body_with_mass(&mut space, position, collider, 12.0);
+
+        let axes = rotation.to_rotation_matrix();
+        let local = Matrix::from_diagonal(&vector![1.0 / 52.0, 1.0 / 40.0, 1.0 / 20.0]);
+        let expected = axes * local * axes.transpose();
+        assert_matrix_eq(&space.inverse_inertia_tensor(handle).unwrap(), &expected);
+    }
+
+    /// Space without gravity holding a 2 kg ball at rest, forces are the only thing moving it.
+    fn space_with_ball() -> (RapierSpace, RigidBodyHandle) {
+        let mut space = RapierSpace::new(Rid::new(1));
+        space.gravity = Vector::zeros();
+        let collider = ColliderBuilder::ball(0.5).build();
+        let handle = body_with_mass(&mut space, Isometry::identity(), collider, 2.0);
+        (space, handle)
+    }
+
+    #[test]
+    fn constant_forces_give_a_constant_acceleration() {
+        let (mut space, handle) = space_with_ball();
+        let dt = space.integration_parameters.dt;
+        for step in 1..=4 {
+            // Constant forces are applied again before every step.
+            space.apply_central_force(handle, vector![4.0, 0.0, 0.0]);
+            space.solve();
+            let velocity = space.rigid_body_set[handle].linvel().x;
+            assert!((velocity - step as Real * dt * 4.0 / 2.0).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn one_shot_forces_only_last_one_step() {
+        let (mut space, handle) = space_with_ball();
+        let dt = space.integration_parameters.dt;
+        space.apply_central_force(handle, vector![4.0, 0.0, 0.0]);
+        space.apply_torque(handle, vector![0.0, 1.0, 0.0]);
+        for _ in 0..4 {
+            space.solve();
+            let body = &space.rigid_body_set[handle];
+            assert!((body.linvel().x - dt * 4.0 / 2.0).abs() < 1.0e-5);
+            assert_eq!(body.user_force(), Vector::zeros());
+            assert_eq!(body.user_torque(), Vector::zeros());
+        }
+    }
+
+    #[test]
+    fn bodies_resting_on_the_floor_fall_asleep() {
+        let mut space = space_with_floor();
+        let mut body = RigidBodyBuilder::dynamic()
+            .translation(vector![0.0, 1.0, 0.0])
+            .build();
+        space.sleep_parameters.apply(body.activation_mut(), true);
+        let handle = space.rigid_body_set.insert(body);
+        space.add_body_collider(handle, ColliderBuilder::cuboid(0.5, 0.5, 0.5).build());
+
+        let mut fell_asleep = 0;
+        for _ in 0..120 {
+            // What the body integrates every step before the solver runs.
+            let velocity = *space.rigid_body_set[handle].linvel();
+            space.set_linear_velocity(handle, velocity, false);
+            space.apply_gravity(handle, vector![0.0, -9.8, 0.0]);
+            for (stepped, sleeping) in space.step_simulation() {
+                assert_eq!(stepped, handle);
+                if sleeping {
+                    fell_asleep += 1;
+                }
+            }
+        }
+        assert!(space.rigid_body_set[handle].is_sleeping());
+        // Asleep bodies are no longer active, the change is only reported once.
+        assert_eq!(fell_asleep, 1);
+    }
+}